@@ -1,60 +1,318 @@
-use std::fs;
+use std::{convert::TryFrom, env, fmt, fs};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SurfacePolicy {
+    // Any command that would take the submarine above the surface leaves it at depth 0.
+    Clamp,
+    // Any command that would take the submarine above the surface is rejected.
+    Error,
+    // Negative depths are allowed, i.e. the submarine can fly.
+    AllowNegative,
+}
+
+#[derive(Debug, PartialEq)]
+struct SurfaceError {
+    command_idx: usize,
+    command: Command,
+    depth: i64,
+}
+
+impl fmt::Display for SurfaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "command #{} ({:?}) would surface the submarine to depth {}",
+            self.command_idx, self.command, self.depth
+        )
+    }
+}
+
+impl SurfacePolicy {
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "clamp" => Some(SurfacePolicy::Clamp),
+            "error" => Some(SurfacePolicy::Error),
+            "allow-negative" => Some(SurfacePolicy::AllowNegative),
+            _ => None,
+        }
+    }
+
+    // Returns the depth the submarine should end up at after `command` attempted to move it to
+    // `depth`.
+    fn apply(
+        &self,
+        depth: i64,
+        command: &Command,
+        command_idx: usize,
+    ) -> Result<i64, SurfaceError> {
+        if depth >= 0 {
+            return Ok(depth);
+        }
+        match self {
+            SurfacePolicy::Clamp => Ok(0),
+            SurfacePolicy::Error => Err(SurfaceError {
+                command_idx,
+                command: *command,
+                depth,
+            }),
+            SurfacePolicy::AllowNegative => Ok(depth),
+        }
+    }
+}
 
 struct Submarine1 {
-    horizontal: u32,
-    depth: u32,
+    horizontal: i64,
+    depth: i64,
+    policy: SurfacePolicy,
+    commands_executed: usize,
 }
 
 impl Submarine1 {
     fn new() -> Self {
+        Self::with_policy(SurfacePolicy::Error)
+    }
+}
+
+impl Steering for Submarine1 {
+    fn with_policy(policy: SurfacePolicy) -> Self {
         Self {
             horizontal: 0,
             depth: 0,
+            policy,
+            commands_executed: 0,
         }
     }
 
-    fn execute(&mut self, command: &Command) {
+    fn execute(&mut self, command: &Command) -> Result<(), SurfaceError> {
+        let command_idx = self.commands_executed;
+        self.commands_executed += 1;
         match command {
-            Command::Forward(amount) => self.horizontal += amount,
-            Command::Down(amount) => self.depth += amount,
-            Command::Up(amount) => self.depth -= amount,
+            Command::Forward(amount) => self.horizontal += i64::from(*amount),
+            Command::Down(amount) => self.depth += i64::from(*amount),
+            Command::Up(amount) => {
+                let depth = self.depth - i64::from(*amount);
+                self.depth = self.policy.apply(depth, command, command_idx)?;
+            }
         }
+        Ok(())
+    }
+
+    fn position(&self) -> (i64, i64) {
+        (self.horizontal, self.depth)
+    }
+
+    fn candidate_plan(target: (i64, i64), _objective: PlanObjective) -> Option<Vec<Command>> {
+        let (horizontal, depth) = target;
+        let mut commands = Vec::new();
+        push_forward(&mut commands, horizontal)?;
+        push_vertical(&mut commands, depth);
+        Some(commands)
     }
 }
 
 struct Submarine2 {
-    horizontal: u32,
-    depth: u32,
-    aim: u32,
+    horizontal: i64,
+    depth: i64,
+    aim: i64,
+    policy: SurfacePolicy,
+    commands_executed: usize,
 }
 
 impl Submarine2 {
     fn new() -> Self {
+        Self::with_policy(SurfacePolicy::Error)
+    }
+}
+
+impl Steering for Submarine2 {
+    fn with_policy(policy: SurfacePolicy) -> Self {
         Self {
             horizontal: 0,
             depth: 0,
             aim: 0,
+            policy,
+            commands_executed: 0,
         }
     }
 
-    fn execute(&mut self, command: &Command) {
+    fn execute(&mut self, command: &Command) -> Result<(), SurfaceError> {
+        let command_idx = self.commands_executed;
+        self.commands_executed += 1;
         match command {
             Command::Forward(amount) => {
+                let amount = i64::from(*amount);
+                let depth = self.depth + self.aim * amount;
+                self.depth = self.policy.apply(depth, command, command_idx)?;
                 self.horizontal += amount;
-                self.depth += self.aim * amount;
             }
-            Command::Down(amount) => self.aim += amount,
-            Command::Up(amount) => self.aim -= amount,
+            Command::Down(amount) => self.aim += i64::from(*amount),
+            Command::Up(amount) => self.aim -= i64::from(*amount),
+        }
+        Ok(())
+    }
+
+    fn position(&self) -> (i64, i64) {
+        (self.horizontal, self.depth)
+    }
+
+    // Depth only changes while moving forward, by the aim at that time.  Every plan below keeps
+    // the aim monotonic so the submarine never crosses the surface on its way to the target.
+    fn candidate_plan(target: (i64, i64), objective: PlanObjective) -> Option<Vec<Command>> {
+        let (horizontal, depth) = target;
+        let mut commands = Vec::new();
+        if horizontal == 0 || depth == 0 {
+            if depth != 0 {
+                return None;
+            }
+            push_forward(&mut commands, horizontal)?;
+            return Some(commands);
+        }
+
+        let sign = depth.signum();
+        let quotient = depth.abs() / horizontal;
+        let remainder = depth.abs() % horizontal;
+        if remainder == 0 {
+            push_vertical(&mut commands, sign * quotient);
+            push_forward(&mut commands, horizontal)?;
+            return Some(commands);
         }
+
+        match objective {
+            // Cruise at zero aim, then cover the whole depth over the last `tail` units, where
+            // `tail` divides the depth and still leaves room to cruise.  Every step fits a single
+            // command for small targets, making the largest such divisor as good as any; past
+            // that, the one splitting into the fewest commands wins.
+            PlanObjective::FewestCommands => {
+                let tail = divisors_below(depth.abs(), horizontal)
+                    .into_iter()
+                    .min_by_key(|&tail| {
+                        let count = command_count(horizontal - tail)
+                            + command_count(depth / tail)
+                            + command_count(tail);
+                        (count, -tail)
+                    })
+                    .unwrap();
+                push_forward(&mut commands, horizontal - tail)?;
+                push_vertical(&mut commands, depth / tail);
+                push_forward(&mut commands, tail)?;
+            }
+            // The total aim change is at least ceil(depth / horizontal), so spend the first part
+            // of the trip at one less than that and the last `remainder` units at exactly that.
+            PlanObjective::LeastEffort => {
+                push_vertical(&mut commands, sign * quotient);
+                push_forward(&mut commands, horizontal - remainder)?;
+                push_vertical(&mut commands, sign);
+                push_forward(&mut commands, remainder)?;
+            }
+        }
+        Some(commands)
     }
 }
 
+trait Steering: Sized {
+    fn with_policy(policy: SurfacePolicy) -> Self;
+
+    fn execute(&mut self, command: &Command) -> Result<(), SurfaceError>;
+
+    fn position(&self) -> (i64, i64);
+
+    // A command sequence that should take a fresh submarine to `target`, or `None` if this
+    // steering model can't get there at all.  Callers should go through `plan`, which verifies it.
+    fn candidate_plan(target: (i64, i64), objective: PlanObjective) -> Option<Vec<Command>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PlanObjective {
+    // Use as few commands as possible.
+    FewestCommands,
+    // Minimize the sum of all command amounts.
+    LeastEffort,
+}
+
+fn plan<S: Steering>(
+    target: (i64, i64),
+    policy: SurfacePolicy,
+    objective: PlanObjective,
+) -> Option<Vec<Command>> {
+    let commands = S::candidate_plan(target, objective)?;
+    let mut sub = S::with_policy(policy);
+    for command in &commands {
+        sub.execute(command).ok()?;
+    }
+    if sub.position() == target {
+        Some(commands)
+    } else {
+        None
+    }
+}
+
+fn effort(commands: &[Command]) -> u64 {
+    commands.iter().map(|c| u64::from(c.amount())).sum()
+}
+
+// Steps longer than a single command can carry are split over several commands.
+fn push_forward(commands: &mut Vec<Command>, amount: i64) -> Option<()> {
+    if amount < 0 {
+        return None;
+    }
+    push_split(commands, amount.unsigned_abs(), Command::Forward);
+    Some(())
+}
+
+fn push_vertical(commands: &mut Vec<Command>, amount: i64) {
+    let command = if amount < 0 {
+        Command::Up
+    } else {
+        Command::Down
+    };
+    push_split(commands, amount.unsigned_abs(), command);
+}
+
+fn push_split(commands: &mut Vec<Command>, mut amount: u64, command: fn(u32) -> Command) {
+    while amount > 0 {
+        let step = u32::try_from(amount).unwrap_or(u32::MAX);
+        commands.push(command(step));
+        amount -= u64::from(step);
+    }
+}
+
+// How many commands `push_forward` or `push_vertical` emit for a step of `amount`.
+fn command_count(amount: i64) -> u64 {
+    let max_step = u64::from(u32::MAX);
+    amount.unsigned_abs().div_ceil(max_step)
+}
+
+fn divisors_below(value: i64, bound: i64) -> Vec<i64> {
+    let mut divisors = Vec::new();
+    let mut candidate = 1;
+    while candidate * candidate <= value {
+        if value % candidate == 0 {
+            for divisor in [candidate, value / candidate] {
+                if divisor < bound && !divisors.contains(&divisor) {
+                    divisors.push(divisor);
+                }
+            }
+        }
+        candidate += 1;
+    }
+    divisors
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum Command {
     Forward(u32),
     Down(u32),
     Up(u32),
 }
 
+impl Command {
+    fn amount(&self) -> u32 {
+        match self {
+            Command::Forward(amount) | Command::Down(amount) | Command::Up(amount) => *amount,
+        }
+    }
+}
+
 impl From<&str> for Command {
     fn from(string: &str) -> Self {
         let parts: Vec<&str> = string.split(' ').collect();
@@ -72,6 +330,45 @@ impl From<&str> for Command {
 fn main() {
     println!("{}", part1());
     println!("{}", part2());
+    // --plan <horizontal> <depth> [--policy clamp|error|allow-negative] [--least-effort]
+    let args: Vec<String> = env::args().collect();
+    if let Some(idx) = args.iter().position(|arg| arg == "--plan") {
+        let coordinate = |offset: usize| -> i64 {
+            args.get(idx + offset)
+                .and_then(|arg| arg.parse().ok())
+                .expect("Expected --plan <horizontal> <depth>")
+        };
+        let target = (coordinate(1), coordinate(2));
+        let policy =
+            args.iter()
+                .position(|arg| arg == "--policy")
+                .map_or(SurfacePolicy::Error, |idx| {
+                    args.get(idx + 1)
+                        .and_then(|arg| SurfacePolicy::from_arg(arg))
+                        .expect("Unknown surface policy")
+                });
+        let objective = if args.iter().any(|arg| arg == "--least-effort") {
+            PlanObjective::LeastEffort
+        } else {
+            PlanObjective::FewestCommands
+        };
+        println!();
+        print_plan("part 1", plan::<Submarine1>(target, policy, objective));
+        print_plan("part 2", plan::<Submarine2>(target, policy, objective));
+    }
+}
+
+fn print_plan(label: &str, commands: Option<Vec<Command>>) {
+    match commands {
+        Some(commands) => println!(
+            "{}: {} commands, effort {}: {:?}",
+            label,
+            commands.len(),
+            effort(&commands),
+            commands
+        ),
+        None => println!("{}: unreachable", label),
+    }
 }
 
 fn read_input() -> Vec<Command> {
@@ -79,20 +376,20 @@ fn read_input() -> Vec<Command> {
     commands.split('\n').map(|s| s.into()).collect()
 }
 
-fn part1() -> u32 {
+fn part1() -> i64 {
     let commands = read_input();
     let mut sub = Submarine1::new();
     for command in commands {
-        sub.execute(&command);
+        sub.execute(&command).unwrap();
     }
     sub.horizontal * sub.depth
 }
 
-fn part2() -> u32 {
+fn part2() -> i64 {
     let commands = read_input();
     let mut sub = Submarine2::new();
     for command in commands {
-        sub.execute(&command);
+        sub.execute(&command).unwrap();
     }
     sub.horizontal * sub.depth
 }
@@ -110,4 +407,201 @@ mod tests {
     fn test_part_2() {
         assert_eq!(part2(), 1_947_878_632);
     }
+
+    #[test]
+    fn test_surface_policies() {
+        let commands = [Command::Down(3), Command::Forward(2), Command::Up(5)];
+
+        let mut sub = Submarine1::with_policy(SurfacePolicy::Clamp);
+        for command in &commands {
+            sub.execute(command).unwrap();
+        }
+        assert_eq!((sub.horizontal, sub.depth), (2, 0));
+
+        let mut sub = Submarine1::with_policy(SurfacePolicy::AllowNegative);
+        for command in &commands {
+            sub.execute(command).unwrap();
+        }
+        assert_eq!((sub.horizontal, sub.depth), (2, -2));
+
+        let mut sub = Submarine1::with_policy(SurfacePolicy::Error);
+        sub.execute(&commands[0]).unwrap();
+        sub.execute(&commands[1]).unwrap();
+        let err = sub.execute(&commands[2]).unwrap_err();
+        assert_eq!(
+            err,
+            SurfaceError {
+                command_idx: 2,
+                command: Command::Up(5),
+                depth: -2,
+            }
+        );
+        assert_eq!(sub.depth, 3);
+    }
+
+    #[test]
+    fn test_surface_policy_with_aim() {
+        let commands = [Command::Up(2), Command::Forward(3)];
+
+        let mut sub = Submarine2::with_policy(SurfacePolicy::Error);
+        sub.execute(&commands[0]).unwrap();
+        assert_eq!(sub.aim, -2);
+        let err = sub.execute(&commands[1]).unwrap_err();
+        assert_eq!(err.command_idx, 1);
+        assert_eq!(err.depth, -6);
+        assert_eq!((sub.horizontal, sub.depth), (0, 0));
+
+        let mut sub = Submarine2::with_policy(SurfacePolicy::AllowNegative);
+        for command in &commands {
+            sub.execute(command).unwrap();
+        }
+        assert_eq!((sub.horizontal, sub.depth), (3, -6));
+    }
+
+    #[test]
+    fn test_plan_submarine_1() {
+        let commands = plan::<Submarine1>(
+            (15, 10),
+            SurfacePolicy::Error,
+            PlanObjective::FewestCommands,
+        );
+        assert_eq!(
+            commands,
+            Some(vec![Command::Forward(15), Command::Down(10)])
+        );
+
+        assert_eq!(
+            plan::<Submarine1>(
+                (-1, 10),
+                SurfacePolicy::Error,
+                PlanObjective::FewestCommands
+            ),
+            None
+        );
+        assert_eq!(
+            plan::<Submarine1>((4, -3), SurfacePolicy::Error, PlanObjective::FewestCommands),
+            None
+        );
+        assert_eq!(
+            plan::<Submarine1>(
+                (4, -3),
+                SurfacePolicy::AllowNegative,
+                PlanObjective::FewestCommands
+            ),
+            Some(vec![Command::Forward(4), Command::Up(3)])
+        );
+    }
+
+    #[test]
+    fn test_plan_submarine_2() {
+        let commands =
+            plan::<Submarine2>((5, 20), SurfacePolicy::Error, PlanObjective::FewestCommands);
+        assert_eq!(commands, Some(vec![Command::Down(4), Command::Forward(5)]));
+
+        assert_eq!(
+            plan::<Submarine2>((0, 20), SurfacePolicy::Error, PlanObjective::FewestCommands),
+            None
+        );
+
+        let fewest = plan::<Submarine2>(
+            (15, 60),
+            SurfacePolicy::Error,
+            PlanObjective::FewestCommands,
+        );
+        assert_eq!(fewest, Some(vec![Command::Down(4), Command::Forward(15)]));
+
+        let fewest = plan::<Submarine2>(
+            (10, 23),
+            SurfacePolicy::Error,
+            PlanObjective::FewestCommands,
+        )
+        .unwrap();
+        assert_eq!(fewest.len(), 3);
+        let cheapest =
+            plan::<Submarine2>((10, 23), SurfacePolicy::Error, PlanObjective::LeastEffort).unwrap();
+        assert_eq!(cheapest.len(), 4);
+        assert_eq!(effort(&cheapest), 13);
+        assert!(effort(&cheapest) < effort(&fewest));
+
+        let flying = plan::<Submarine2>(
+            (10, -23),
+            SurfacePolicy::AllowNegative,
+            PlanObjective::LeastEffort,
+        );
+        assert_eq!(
+            flying,
+            Some(vec![
+                Command::Up(2),
+                Command::Forward(7),
+                Command::Up(1),
+                Command::Forward(3)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_plan_large_steps() {
+        let max = u32::MAX;
+        assert_eq!(
+            plan::<Submarine1>(
+                (0, 5_000_000_000),
+                SurfacePolicy::Error,
+                PlanObjective::FewestCommands
+            ),
+            Some(vec![Command::Down(max), Command::Down(705_032_705)])
+        );
+        assert_eq!(
+            plan::<Submarine2>(
+                (10_000_000_000, 20_000_000_000),
+                SurfacePolicy::Error,
+                PlanObjective::FewestCommands
+            ),
+            Some(vec![
+                Command::Down(2),
+                Command::Forward(max),
+                Command::Forward(max),
+                Command::Forward(1_410_065_410),
+            ])
+        );
+
+        // The largest tail, the depth itself, takes five commands; a tail of 1_227_133_514 takes
+        // four.
+        let target = (9_000_000_000, 8_589_934_598);
+        for objective in [PlanObjective::FewestCommands, PlanObjective::LeastEffort] {
+            let commands = plan::<Submarine2>(target, SurfacePolicy::Error, objective).unwrap();
+            let mut sub = Submarine2::new();
+            for command in &commands {
+                sub.execute(command).unwrap();
+            }
+            assert_eq!(sub.position(), target);
+        }
+        let fewest =
+            plan::<Submarine2>(target, SurfacePolicy::Error, PlanObjective::FewestCommands)
+                .unwrap();
+        assert_eq!(fewest.len(), 4);
+        assert_eq!(fewest.last(), Some(&Command::Forward(1_227_133_514)));
+    }
+
+    #[test]
+    fn test_plan_reaches_puzzle_answers() {
+        fn replay<S: Steering>(commands: &[Command]) -> (i64, i64) {
+            let mut sub = S::with_policy(SurfacePolicy::Error);
+            for command in commands {
+                sub.execute(command).unwrap();
+            }
+            sub.position()
+        }
+
+        fn check<S: Steering>(answer: i64) {
+            let target = replay::<S>(&read_input());
+            assert_eq!(target.0 * target.1, answer);
+            for objective in [PlanObjective::FewestCommands, PlanObjective::LeastEffort] {
+                let commands = plan::<S>(target, SurfacePolicy::Error, objective).unwrap();
+                assert_eq!(replay::<S>(&commands), target);
+            }
+        }
+
+        check::<Submarine1>(1_938_402);
+        check::<Submarine2>(1_947_878_632);
+    }
 }
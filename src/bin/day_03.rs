@@ -1,28 +1,38 @@
-use std::{fs, ops::Index};
+use std::{cmp::Ordering, env, fmt, fs, ops::Index};
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 enum Bit {
     Zero,
     One,
 }
 
-#[derive(Clone)]
-struct BinNum(u32);
+const MAX_WIDTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+struct BinNum {
+    value: u64,
+    width: usize,
+}
 
 impl BinNum {
-    fn bits(&self) -> Bits {
+    fn bits(&self) -> Bits<'_> {
         Bits {
             value: self,
             next_bit_idx: 0,
         }
     }
 
-    fn value(&self) -> u32 {
-        self.0
+    fn value(&self) -> u64 {
+        self.value
+    }
+
+    fn width(&self) -> usize {
+        self.width
     }
 
+    // Bits are indexed from the most significant digit of the number as written.
     fn bit(&self, bit_idx: usize) -> Bit {
-        if self.value() & ((1 << 11) >> bit_idx) > 0 {
+        if (self.value >> (self.width - 1 - bit_idx)) & 1 == 1 {
             Bit::One
         } else {
             Bit::Zero
@@ -32,10 +42,92 @@ impl BinNum {
 
 impl From<&str> for BinNum {
     fn from(s: &str) -> Self {
-        BinNum(u32::from_str_radix(s, 2).unwrap())
+        let s = s.trim();
+        assert!(s.len() <= MAX_WIDTH);
+        BinNum {
+            value: u64::from_str_radix(s, 2).unwrap(),
+            width: s.len(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum DiagnosticError {
+    Empty,
+    InvalidDigit {
+        line: usize,
+    },
+    TooWide {
+        line: usize,
+        width: usize,
+    },
+    InconsistentWidth {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for DiagnosticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiagnosticError::Empty => write!(f, "no diagnostic numbers"),
+            DiagnosticError::InvalidDigit { line } => {
+                write!(f, "line {} is not a binary number", line)
+            }
+            DiagnosticError::TooWide { line, width } => write!(
+                f,
+                "line {} is {} bits wide, at most {} are supported",
+                line, width, MAX_WIDTH
+            ),
+            DiagnosticError::InconsistentWidth {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {} is {} bits wide, expected {}",
+                line, found, expected
+            ),
+        }
     }
 }
 
+// Parses one binary number per non-empty line, inferring the bit width from the first one.
+fn parse_diagnostics(input: &str) -> Result<Vec<BinNum>, DiagnosticError> {
+    let mut numbers: Vec<BinNum> = Vec::new();
+    for (idx, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let line_no = idx + 1;
+        if !line.chars().all(|c| c == '0' || c == '1') {
+            return Err(DiagnosticError::InvalidDigit { line: line_no });
+        }
+        if line.len() > MAX_WIDTH {
+            return Err(DiagnosticError::TooWide {
+                line: line_no,
+                width: line.len(),
+            });
+        }
+        if let Some(first) = numbers.first() {
+            if first.width() != line.len() {
+                return Err(DiagnosticError::InconsistentWidth {
+                    line: line_no,
+                    expected: first.width(),
+                    found: line.len(),
+                });
+            }
+        }
+        numbers.push(line.into());
+    }
+    if numbers.is_empty() {
+        return Err(DiagnosticError::Empty);
+    }
+    Ok(numbers)
+}
+
 struct Bits<'a> {
     value: &'a BinNum,
     next_bit_idx: usize,
@@ -45,7 +137,7 @@ impl<'a> Iterator for Bits<'a> {
     type Item = Bit;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.next_bit_idx >= 12 {
+        if self.next_bit_idx >= self.value.width() {
             return None;
         }
         let result = self.value.bit(self.next_bit_idx);
@@ -54,21 +146,17 @@ impl<'a> Iterator for Bits<'a> {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct DigitFrequency {
     zeroes: u32,
     ones: u32,
 }
 
 impl DigitFrequency {
-    fn new() -> Self {
-        Self { zeroes: 0, ones: 0 }
-    }
-
-    fn incr(&mut self, value: Bit) {
+    fn decr(&mut self, value: Bit) {
         match value {
-            Bit::Zero => self.zeroes += 1,
-            Bit::One => self.ones += 1,
+            Bit::Zero => self.zeroes -= 1,
+            Bit::One => self.ones -= 1,
         }
     }
 }
@@ -84,93 +172,465 @@ impl Index<Bit> for DigitFrequency {
     }
 }
 
+// Per-column counts of set bits, stored bit-sliced: bit `pos` of `slices[j]` is bit `j` of the
+// number of ones seen at bit position `pos`.  Adding a number is a ripple-carry add into all 64
+// columns at once, which on average only touches the lowest couple of slices.
+struct SlicedCounter {
+    slices: [u64; 32],
+}
+
+impl SlicedCounter {
+    fn new() -> Self {
+        Self { slices: [0; 32] }
+    }
+
+    fn add(&mut self, value: u64) {
+        let mut carry = value;
+        for slice in self.slices.iter_mut() {
+            if carry == 0 {
+                break;
+            }
+            let sum = *slice ^ carry;
+            carry &= *slice;
+            *slice = sum;
+        }
+        assert_eq!(carry, 0, "column count overflow");
+    }
+
+    fn ones(&self, pos: usize) -> u32 {
+        self.slices
+            .iter()
+            .enumerate()
+            .map(|(j, slice)| (((slice >> pos) & 1) as u32) << j)
+            .sum()
+    }
+}
+
 struct DigitCounter {
-    frequencies: [DigitFrequency; 12],
+    frequencies: Vec<DigitFrequency>,
     total: u32,
 }
 
 impl DigitCounter {
     fn count(bin_nums: &[BinNum]) -> Self {
-        let mut counter = Self {
-            frequencies: [DigitFrequency::new(); 12],
-            total: 0,
-        };
-
+        let width = bin_nums.first().map_or(0, BinNum::width);
+        let mut ones = SlicedCounter::new();
         for bin_num in bin_nums {
-            for (idx, value) in bin_num.bits().enumerate() {
-                counter.frequencies[idx].incr(value);
-                counter.total += 1;
-            }
+            ones.add(bin_num.value());
         }
 
-        counter
+        let len = bin_nums.len() as u32;
+        let frequencies = (0..width)
+            .map(|idx| {
+                let ones = ones.ones(width - 1 - idx);
+                DigitFrequency {
+                    zeroes: len - ones,
+                    ones,
+                }
+            })
+            .collect();
+
+        Self {
+            frequencies,
+            total: len * width as u32,
+        }
+    }
+
+    // Updates the counts as if `bin_num` had never been counted.
+    fn remove(&mut self, bin_num: &BinNum) {
+        for (idx, value) in bin_num.bits().enumerate() {
+            self.frequencies[idx].decr(value);
+            self.total -= 1;
+        }
     }
 }
 
 fn main() {
     println!("{}", part1());
     println!("{}", part2());
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|arg| arg == "--report") {
+        println!();
+        println!("{}", DiagnosticReport::new(&read_input()));
+    }
+    // --explain most|least 0|1 traces a rating with a custom selection policy.
+    if let Some(idx) = args.iter().position(|arg| arg == "--explain") {
+        let selection = match args.get(idx + 1).map(String::as_str) {
+            Some("most") => Selection::MostCommon,
+            Some("least") => Selection::LeastCommon,
+            _ => panic!("Expected --explain most|least 0|1"),
+        };
+        let on_tie = match args.get(idx + 2).map(String::as_str) {
+            Some("0") => Bit::Zero,
+            Some("1") => Bit::One,
+            _ => panic!("Expected --explain most|least 0|1"),
+        };
+        println!();
+        println!(
+            "{}",
+            explain_rating(&read_input(), SelectionPolicy::new(selection, on_tie))
+        );
+    }
 }
 
 fn read_input() -> Vec<BinNum> {
     let bit_strings = fs::read_to_string("data/day_03.txt").expect("File missing");
-    bit_strings.lines().map(|line| line.into()).collect()
+    parse_diagnostics(&bit_strings).unwrap()
 }
 
-fn part1() -> u32 {
+fn part1() -> u128 {
     let numbers = read_input();
-    let counter = DigitCounter::count(&numbers);
-    let mut gamma = 0u32;
-    let mut epsilon = 0u32;
+    power_consumption(&numbers)
+}
+
+// Both factors are below 2^64, so the product always fits.
+fn power_consumption(numbers: &[BinNum]) -> u128 {
+    let (gamma, epsilon) = gamma_and_epsilon(&DigitCounter::count(numbers));
+    gamma as u128 * epsilon as u128
+}
+
+// Tied columns count towards epsilon.
+fn gamma_and_epsilon(counter: &DigitCounter) -> (u64, u64) {
+    let mut gamma = 0u64;
+    let mut epsilon = 0u64;
     for (idx, freq) in counter.frequencies.iter().rev().enumerate() {
-        let digit_value = 2u32.pow(idx as u32);
+        let digit_value = 1u64 << idx;
         if freq[Bit::One] > freq[Bit::Zero] {
             gamma += digit_value;
         } else {
             epsilon += digit_value;
         }
     }
-    gamma * epsilon
+    (gamma, epsilon)
 }
 
-fn part2() -> u32 {
+fn part2() -> u128 {
     let numbers = read_input();
-    let o2_rating = rating(&numbers, |freq| {
-        if freq[Bit::One] >= freq[Bit::Zero] {
-            Bit::One
-        } else {
-            Bit::Zero
+    life_support_rating(&numbers)
+}
+
+fn life_support_rating(numbers: &[BinNum]) -> u128 {
+    let trie = BinTrie::build(numbers);
+    let o2_rating = trie.rating(SelectionPolicy::O2).unwrap();
+    let co2_rating = trie.rating(SelectionPolicy::CO2).unwrap();
+    o2_rating as u128 * co2_rating as u128
+}
+
+// Everything day 3 can tell about a set of diagnostic numbers, not just the two puzzle answers.
+struct DiagnosticReport {
+    width: usize,
+    gamma: u64,
+    epsilon: u64,
+    o2: Option<u64>,
+    co2: Option<u64>,
+    frequencies: Vec<DigitFrequency>,
+    tied_columns: Vec<usize>,
+}
+
+impl DiagnosticReport {
+    fn new(numbers: &[BinNum]) -> Self {
+        let counter = DigitCounter::count(numbers);
+        let (gamma, epsilon) = gamma_and_epsilon(&counter);
+        let tied_columns = counter
+            .frequencies
+            .iter()
+            .enumerate()
+            .filter(|(_, freq)| freq[Bit::Zero] == freq[Bit::One])
+            .map(|(idx, _)| idx)
+            .collect();
+        Self {
+            width: counter.frequencies.len(),
+            gamma,
+            epsilon,
+            o2: rating(numbers, SelectionPolicy::O2),
+            co2: rating(numbers, SelectionPolicy::CO2),
+            frequencies: counter.frequencies,
+            tied_columns,
         }
-    });
-    let co2_rating = rating(&numbers, |freq| {
-        if freq[Bit::One] < freq[Bit::Zero] {
-            Bit::One
-        } else {
-            Bit::Zero
+    }
+
+    fn power_consumption(&self) -> u128 {
+        self.gamma as u128 * self.epsilon as u128
+    }
+
+    fn life_support_rating(&self) -> Option<u128> {
+        Some(self.o2? as u128 * self.co2? as u128)
+    }
+
+    fn binary(&self, value: u64) -> String {
+        format!("{:0width$b}", value, width = self.width)
+    }
+}
+
+impl fmt::Display for DiagnosticReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values = [
+            ("gamma", Some(self.gamma)),
+            ("epsilon", Some(self.epsilon)),
+            ("o2", self.o2),
+            ("co2", self.co2),
+        ];
+        writeln!(f, "{:<8} {:>20}  binary", "value", "decimal")?;
+        for (name, value) in &values {
+            match value {
+                Some(value) => writeln!(f, "{:<8} {:>20}  {}", name, value, self.binary(*value))?,
+                None => writeln!(f, "{:<8} {:>20}  -", name, "-")?,
+            }
         }
-    });
-    o2_rating * co2_rating
+        writeln!(f)?;
+        writeln!(f, "{:>3} {:>8} {:>8}", "bit", "zeroes", "ones")?;
+        for (idx, freq) in self.frequencies.iter().enumerate() {
+            write!(f, "{:>3} {:>8} {:>8}", idx, freq[Bit::Zero], freq[Bit::One])?;
+            if self.tied_columns.contains(&idx) {
+                write!(f, "  tie")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "power consumption: {}", self.power_consumption())?;
+        match self.life_support_rating() {
+            Some(rating) => write!(f, "life support rating: {}", rating),
+            None => write!(f, "life support rating: -"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Selection {
+    MostCommon,
+    LeastCommon,
 }
 
-fn rating(numbers: &[BinNum], bit_selector: fn(DigitFrequency) -> Bit) -> u32 {
+// Decides which bit value survives a round of rating filtering.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SelectionPolicy {
+    selection: Selection,
+    on_tie: Bit,
+}
+
+impl SelectionPolicy {
+    const O2: SelectionPolicy = SelectionPolicy {
+        selection: Selection::MostCommon,
+        on_tie: Bit::One,
+    };
+
+    const CO2: SelectionPolicy = SelectionPolicy {
+        selection: Selection::LeastCommon,
+        on_tie: Bit::Zero,
+    };
+
+    fn new(selection: Selection, on_tie: Bit) -> Self {
+        Self { selection, on_tie }
+    }
+
+    fn select(&self, freq: DigitFrequency) -> Bit {
+        let (most_common, least_common) = match freq[Bit::One].cmp(&freq[Bit::Zero]) {
+            Ordering::Equal => return self.on_tie,
+            Ordering::Greater => (Bit::One, Bit::Zero),
+            Ordering::Less => (Bit::Zero, Bit::One),
+        };
+        match self.selection {
+            Selection::MostCommon => most_common,
+            Selection::LeastCommon => least_common,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct TraceStep {
+    bit_idx: usize,
+    frequency: DigitFrequency,
+    chosen: Bit,
+    remaining: usize,
+}
+
+// Every filtering round of a rating, for comparing against other implementations.
+#[derive(Debug, PartialEq)]
+struct RatingTrace {
+    policy: SelectionPolicy,
+    steps: Vec<TraceStep>,
+    value: Option<u64>,
+}
+
+impl fmt::Display for RatingTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:?}", self.policy)?;
+        writeln!(f, "bit  zeroes    ones  kept  remaining")?;
+        for step in &self.steps {
+            let tie = if step.frequency[Bit::Zero] == step.frequency[Bit::One] {
+                " (tie)"
+            } else {
+                ""
+            };
+            writeln!(
+                f,
+                "{:>3}  {:>6}  {:>6}  {:>4}  {:>9}{}",
+                step.bit_idx,
+                step.frequency[Bit::Zero],
+                step.frequency[Bit::One],
+                step.chosen as u8,
+                step.remaining,
+                tie
+            )?;
+        }
+        match self.value {
+            Some(value) => write!(f, "rating: {}", value),
+            None => write!(f, "rating: none, every number was filtered out"),
+        }
+    }
+}
+
+// Returns `None` if the policy ever keeps a bit value that none of the remaining numbers have.
+fn rating(numbers: &[BinNum], policy: SelectionPolicy) -> Option<u64> {
+    explain_rating(numbers, policy).value
+}
+
+fn explain_rating(numbers: &[BinNum], policy: SelectionPolicy) -> RatingTrace {
+    let width = numbers.first().map_or(0, BinNum::width);
     let mut numbers = numbers.to_vec();
-    for bit_idx in 0..12 {
+    let mut counter = DigitCounter::count(&numbers);
+    let mut steps = Vec::new();
+    for bit_idx in 0..width {
         if numbers.len() == 1 {
             break;
         }
 
-        let counter = DigitCounter::count(&numbers);
         let digit_frequency = counter.frequencies[bit_idx];
-        let bit_value_to_keep = bit_selector(digit_frequency);
+        let bit_value_to_keep = policy.select(digit_frequency);
 
-        numbers.retain(|n| n.bit(bit_idx) == bit_value_to_keep);
+        numbers.retain(|n| {
+            let keep = n.bit(bit_idx) == bit_value_to_keep;
+            if !keep {
+                counter.remove(n);
+            }
+            keep
+        });
+
+        steps.push(TraceStep {
+            bit_idx,
+            frequency: digit_frequency,
+            chosen: bit_value_to_keep,
+            remaining: numbers.len(),
+        });
+    }
+    RatingTrace {
+        policy,
+        steps,
+        value: numbers.first().map(BinNum::value),
+    }
+}
+
+struct TrieNode {
+    children: [Option<usize>; 2],
+    count: u32,
+}
+
+impl TrieNode {
+    fn new() -> Self {
+        Self {
+            children: [None, None],
+            count: 0,
+        }
+    }
+}
+
+// Binary trie over the bits of the numbers, most significant first.  Each node knows how many
+// numbers pass through it, which is exactly the digit frequency `rating` would compute for the
+// numbers still left at that point of the filtering.
+struct BinTrie {
+    nodes: Vec<TrieNode>,
+    width: usize,
+}
+
+impl BinTrie {
+    fn build(numbers: &[BinNum]) -> Self {
+        let mut trie = Self {
+            nodes: vec![TrieNode::new()],
+            width: numbers.first().map_or(0, BinNum::width),
+        };
+        for number in numbers {
+            let mut node_idx = 0;
+            trie.nodes[node_idx].count += 1;
+            for value in number.bits() {
+                let child = match value {
+                    Bit::Zero => 0,
+                    Bit::One => 1,
+                };
+                node_idx = match trie.nodes[node_idx].children[child] {
+                    Some(child_idx) => child_idx,
+                    None => {
+                        trie.nodes.push(TrieNode::new());
+                        let child_idx = trie.nodes.len() - 1;
+                        trie.nodes[node_idx].children[child] = Some(child_idx);
+                        child_idx
+                    }
+                };
+                trie.nodes[node_idx].count += 1;
+            }
+        }
+        trie
+    }
+
+    fn count(&self, node_idx: Option<usize>) -> u32 {
+        node_idx.map_or(0, |idx| self.nodes[idx].count)
+    }
+
+    // Same result as `rating`, found by walking a single path from the root to a leaf.
+    fn rating(&self, policy: SelectionPolicy) -> Option<u64> {
+        if self.nodes[0].count == 0 {
+            return None;
+        }
+        let mut node_idx = 0;
+        let mut value = 0u64;
+        for _ in 0..self.width {
+            let [zeroes, ones] = self.nodes[node_idx].children;
+            let bit = if self.nodes[node_idx].count == 1 {
+                // Only one number left, so there's nothing to filter; just follow it.
+                if ones.is_some() {
+                    Bit::One
+                } else {
+                    Bit::Zero
+                }
+            } else {
+                policy.select(DigitFrequency {
+                    zeroes: self.count(zeroes),
+                    ones: self.count(ones),
+                })
+            };
+            let next = match bit {
+                Bit::Zero => zeroes,
+                Bit::One => ones,
+            };
+            value = (value << 1) | (bit == Bit::One) as u64;
+            node_idx = next?;
+        }
+        Some(value)
     }
-    numbers.first().unwrap().value()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_numbers(rng: &mut StdRng, count: usize, width: usize) -> Vec<BinNum> {
+        (0..count)
+            .map(|_| {
+                let s: String = (0..width)
+                    .map(|_| if rng.gen() { '1' } else { '0' })
+                    .collect();
+                BinNum::from(s.as_str())
+            })
+            .collect()
+    }
+
+    fn frequencies(counter: &DigitCounter) -> Vec<(u32, u32)> {
+        counter
+            .frequencies
+            .iter()
+            .map(|f| (f[Bit::Zero], f[Bit::One]))
+            .collect()
+    }
 
     #[test]
     fn test_bin_num_bits() {
@@ -207,6 +667,186 @@ mod tests {
         assert_eq!(counter.frequencies[6][Bit::Zero], 3);
     }
 
+    fn example() -> Vec<BinNum> {
+        parse_diagnostics(
+            "00100\n11110\n10110\n10111\n10101\n01111\n00111\n11100\n10000\n11001\n00010\n01010\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_example() {
+        let numbers = example();
+        assert_eq!(numbers[0].width(), 5);
+        assert_eq!(DigitCounter::count(&numbers).frequencies.len(), 5);
+        assert_eq!(power_consumption(&numbers), 198);
+        assert_eq!(life_support_rating(&numbers), 230);
+    }
+
+    #[test]
+    fn test_wide_numbers() {
+        let high = format!("{}{}", "1".repeat(32), "0".repeat(32));
+        let low = format!("{}{}", "0".repeat(32), "1".repeat(32));
+        let numbers = parse_diagnostics(&format!("{}\n{}\n{}\n", high, high, low)).unwrap();
+        let expected = ((u32::MAX as u128) << 32) * u32::MAX as u128;
+        assert_eq!(power_consumption(&numbers), expected);
+        assert_eq!(life_support_rating(&numbers), expected);
+        let report = DiagnosticReport::new(&numbers);
+        assert_eq!(report.power_consumption(), expected);
+        assert_eq!(report.life_support_rating(), Some(expected));
+    }
+
+    #[test]
+    fn test_parse_diagnostics_errors() {
+        assert_eq!(parse_diagnostics("\n"), Err(DiagnosticError::Empty));
+        assert_eq!(
+            parse_diagnostics("0101\n01201\n"),
+            Err(DiagnosticError::InvalidDigit { line: 2 })
+        );
+        assert_eq!(
+            parse_diagnostics("0101\n01101\n"),
+            Err(DiagnosticError::InconsistentWidth {
+                line: 2,
+                expected: 4,
+                found: 5
+            })
+        );
+        let too_wide = "1".repeat(65);
+        assert_eq!(
+            parse_diagnostics(&too_wide),
+            Err(DiagnosticError::TooWide { line: 1, width: 65 })
+        );
+        let widest = parse_diagnostics(&"1".repeat(64)).unwrap();
+        assert_eq!(widest[0].value(), u64::MAX);
+        assert_eq!(widest[0].bits().count(), 64);
+    }
+
+    #[test]
+    fn test_sliced_counter_matches_bitwise_count() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for &width in &[1, 5, 12, 63, 64] {
+            let numbers = random_numbers(&mut rng, 1000, width);
+            let mut expected = vec![(0, 0); width];
+            for number in &numbers {
+                for (idx, value) in number.bits().enumerate() {
+                    match value {
+                        Bit::Zero => expected[idx].0 += 1,
+                        Bit::One => expected[idx].1 += 1,
+                    }
+                }
+            }
+            let counter = DigitCounter::count(&numbers);
+            assert_eq!(frequencies(&counter), expected);
+            assert_eq!(counter.total, 1000 * width as u32);
+        }
+    }
+
+    #[test]
+    fn test_counter_remove() {
+        let mut rng = StdRng::seed_from_u64(29);
+        let mut numbers = random_numbers(&mut rng, 500, 16);
+        let mut counter = DigitCounter::count(&numbers);
+        numbers.retain(|n| {
+            let keep = n.value() % 3 != 0;
+            if !keep {
+                counter.remove(n);
+            }
+            keep
+        });
+        let recounted = DigitCounter::count(&numbers);
+        assert_eq!(frequencies(&counter), frequencies(&recounted));
+        assert_eq!(counter.total, recounted.total);
+    }
+
+    #[test]
+    fn test_trie_rating_matches_retain_rating() {
+        let numbers = example();
+        let trie = BinTrie::build(&numbers);
+        assert_eq!(trie.rating(SelectionPolicy::O2), Some(23));
+        assert_eq!(trie.rating(SelectionPolicy::CO2), Some(10));
+
+        let mut rng = StdRng::seed_from_u64(30);
+        for _ in 0..200 {
+            let count = rng.gen_range(1, 200);
+            let width = rng.gen_range(1, 20);
+            let numbers = random_numbers(&mut rng, count, width);
+            let trie = BinTrie::build(&numbers);
+            for &policy in &[SelectionPolicy::O2, SelectionPolicy::CO2] {
+                assert_eq!(trie.rating(policy), rating(&numbers, policy));
+            }
+        }
+    }
+
+    #[test]
+    fn test_selection_policy() {
+        let tie = DigitFrequency { zeroes: 3, ones: 3 };
+        let more_ones = DigitFrequency { zeroes: 2, ones: 4 };
+        assert_eq!(SelectionPolicy::O2.select(tie), Bit::One);
+        assert_eq!(SelectionPolicy::O2.select(more_ones), Bit::One);
+        assert_eq!(SelectionPolicy::CO2.select(tie), Bit::Zero);
+        assert_eq!(SelectionPolicy::CO2.select(more_ones), Bit::Zero);
+
+        let policy = SelectionPolicy::new(Selection::MostCommon, Bit::Zero);
+        assert_eq!(policy.select(tie), Bit::Zero);
+        assert_eq!(policy.select(more_ones), Bit::One);
+        let policy = SelectionPolicy::new(Selection::LeastCommon, Bit::One);
+        assert_eq!(policy.select(tie), Bit::One);
+        assert_eq!(policy.select(more_ones), Bit::Zero);
+
+        let numbers = example();
+        assert_eq!(
+            rating(
+                &numbers,
+                SelectionPolicy::new(Selection::MostCommon, Bit::Zero)
+            ),
+            Some(0b10110)
+        );
+    }
+
+    #[test]
+    fn test_explain_rating() {
+        let trace = explain_rating(&example(), SelectionPolicy::CO2);
+        assert_eq!(trace.value, Some(10));
+        assert_eq!(trace.steps.len(), 3);
+        assert_eq!(
+            trace.steps[0],
+            TraceStep {
+                bit_idx: 0,
+                frequency: DigitFrequency { zeroes: 5, ones: 7 },
+                chosen: Bit::Zero,
+                remaining: 5,
+            }
+        );
+        assert_eq!(
+            trace.steps[2].frequency,
+            DigitFrequency { zeroes: 1, ones: 1 }
+        );
+        assert_eq!(trace.steps[2].chosen, Bit::Zero);
+        assert_eq!(trace.steps[2].remaining, 1);
+        assert!(trace.to_string().ends_with("rating: 10"));
+    }
+
+    #[test]
+    fn test_diagnostic_report() {
+        let report = DiagnosticReport::new(&example());
+        assert_eq!((report.gamma, report.epsilon), (22, 9));
+        assert_eq!(report.binary(report.gamma), "10110");
+        assert_eq!(report.binary(report.epsilon), "01001");
+        assert_eq!((report.o2, report.co2), (Some(23), Some(10)));
+        assert_eq!(report.power_consumption(), 198);
+        assert_eq!(report.life_support_rating(), Some(230));
+        assert_eq!(report.frequencies[0], DigitFrequency { zeroes: 5, ones: 7 });
+        assert!(report.tied_columns.is_empty());
+
+        let report = DiagnosticReport::new(&[BinNum::from("011"), BinNum::from("110")]);
+        assert_eq!(report.tied_columns, vec![0, 2]);
+        assert_eq!((report.gamma, report.epsilon), (0b010, 0b101));
+        let table = report.to_string();
+        assert!(table.contains("gamma                       2  010"));
+        assert!(table.contains("  0        1        1  tie"));
+        assert!(table.contains("  1        0        2\n"));
+    }
+
     #[test]
     fn test_part_1() {
         assert_eq!(part1(), 2_640_986);
@@ -1,10 +1,21 @@
-use itertools::Itertools;
-use std::fs;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    env, fmt, fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    rc::Rc,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Board {
-    values: [u32; 25],
-    markings: [Marking; 25],
+    rows: usize,
+    cols: usize,
+    values: Vec<u32>,
+    markings: Vec<Marking>,
+    win_lines: Rc<WinLines>,
+    // Number of unmarked cells left on each of the win lines.
+    remaining: Vec<usize>,
+    winning_line: Option<usize>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -22,6 +33,7 @@ impl Marking {
     }
 }
 
+#[derive(Debug, PartialEq)]
 struct Position {
     row: usize,
     col: usize,
@@ -29,57 +41,253 @@ struct Position {
 
 impl Position {
     fn new(row: usize, col: usize) -> Self {
-        assert!(row < 5);
-        assert!(col < 5);
         Self { row, col }
     }
+}
 
-    fn to_idx(&self) -> usize {
-        self.row * 5 + self.col
+#[derive(Debug, Clone, PartialEq)]
+enum WinCondition {
+    Rows,
+    Columns,
+    // Both diagonals of a square board.
+    Diagonals,
+    FourCorners,
+    Blackout,
+    // Row-major mask of the cells that all have to be marked.
+    Pattern(Vec<bool>),
+}
+
+#[derive(Debug, PartialEq)]
+enum WinConditionError {
+    NotSquare { rows: usize, cols: usize },
+    PatternSize { expected: usize, found: usize },
+    EmptyPattern,
+}
+
+impl fmt::Display for WinConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WinConditionError::NotSquare { rows, cols } => {
+                write!(f, "diagonals need a square board, not {}x{}", rows, cols)
+            }
+            WinConditionError::PatternSize { expected, found } => write!(
+                f,
+                "pattern has {} cells but the boards have {}",
+                found, expected
+            ),
+            WinConditionError::EmptyPattern => write!(f, "pattern doesn't contain any cells"),
+        }
+    }
+}
+
+impl WinCondition {
+    // Parses a condition name, or a pattern with `/` between its rows.
+    fn from_arg(arg: &str) -> Self {
+        match arg {
+            "rows" => WinCondition::Rows,
+            "columns" => WinCondition::Columns,
+            "diagonals" => WinCondition::Diagonals,
+            "corners" => WinCondition::FourCorners,
+            "blackout" => WinCondition::Blackout,
+            pattern => WinCondition::pattern(&pattern.replace('/', "\n")),
+        }
+    }
+
+    // Parses a pattern drawn with `#` for cells that need marking and `.` for the others, one
+    // board row per line.
+    fn pattern(s: &str) -> Self {
+        WinCondition::Pattern(
+            s.lines()
+                .flat_map(|line| line.trim().chars())
+                .map(|c| c == '#')
+                .collect(),
+        )
+    }
+
+    // The sets of cell indices any of which wins the board when fully marked.
+    fn lines(&self, rows: usize, cols: usize) -> Result<Vec<Vec<usize>>, WinConditionError> {
+        let idx = |row: usize, col: usize| row * cols + col;
+        let lines = match self {
+            WinCondition::Rows => (0..rows)
+                .map(|row| (0..cols).map(|col| idx(row, col)).collect())
+                .collect(),
+            WinCondition::Columns => (0..cols)
+                .map(|col| (0..rows).map(|row| idx(row, col)).collect())
+                .collect(),
+            WinCondition::Diagonals => {
+                if rows != cols {
+                    return Err(WinConditionError::NotSquare { rows, cols });
+                }
+                vec![
+                    (0..rows).map(|i| idx(i, i)).collect(),
+                    (0..rows).map(|i| idx(i, cols - 1 - i)).collect(),
+                ]
+            }
+            WinCondition::FourCorners => {
+                let mut corners = vec![
+                    idx(0, 0),
+                    idx(0, cols - 1),
+                    idx(rows - 1, 0),
+                    idx(rows - 1, cols - 1),
+                ];
+                corners.sort_unstable();
+                corners.dedup();
+                vec![corners]
+            }
+            WinCondition::Blackout => vec![(0..rows * cols).collect()],
+            WinCondition::Pattern(mask) => {
+                if mask.len() != rows * cols {
+                    return Err(WinConditionError::PatternSize {
+                        expected: rows * cols,
+                        found: mask.len(),
+                    });
+                }
+                let cells: Vec<usize> = (0..mask.len()).filter(|&idx| mask[idx]).collect();
+                if cells.is_empty() {
+                    return Err(WinConditionError::EmptyPattern);
+                }
+                vec![cells]
+            }
+        };
+        Ok(lines)
+    }
+}
+
+// All the win lines for boards of one size, plus the reverse mapping from each cell to the lines
+// going through it so marking a cell only has to touch those.
+#[derive(Debug)]
+struct WinLines {
+    lines: Vec<Vec<usize>>,
+    cell_lines: Vec<Vec<usize>>,
+}
+
+impl WinLines {
+    fn new(
+        rows: usize,
+        cols: usize,
+        conditions: &[WinCondition],
+    ) -> Result<Self, WinConditionError> {
+        let mut lines = Vec::new();
+        for condition in conditions {
+            lines.extend(condition.lines(rows, cols)?);
+        }
+        let mut cell_lines = vec![Vec::new(); rows * cols];
+        for (line_idx, line) in lines.iter().enumerate() {
+            for &cell in line {
+                cell_lines[cell].push(line_idx);
+            }
+        }
+        Ok(Self { lines, cell_lines })
     }
 }
 
 impl Board {
-    fn new(values: &[u32]) -> Self {
+    fn new(rows: usize, cols: usize, values: &[u32]) -> Self {
+        assert_eq!(values.len(), rows * cols);
+        let win_lines = WinLines::new(rows, cols, &[WinCondition::Rows, WinCondition::Columns])
+            .expect("rows and columns fit any board");
         let mut result = Board {
-            values: Default::default(),
-            markings: [Marking::Unmarked; 25],
+            rows,
+            cols,
+            values: values.to_vec(),
+            markings: vec![Marking::Unmarked; values.len()],
+            win_lines: Rc::new(win_lines),
+            remaining: Vec::new(),
+            winning_line: None,
         };
-        result.values.copy_from_slice(values);
+        result.reset_counters();
         result
     }
 
-    fn mark(&mut self, value: u32) {
-        for (idx, v) in self.values.iter().enumerate() {
-            if *v == value {
-                self.markings[idx] = Marking::Marked
-            }
-        }
+    fn set_win_lines(&mut self, win_lines: Rc<WinLines>) {
+        assert_eq!(win_lines.cell_lines.len(), self.values.len());
+        self.win_lines = win_lines;
+        self.reset_counters();
     }
 
-    fn has_marked_all(&self, positions: &[Position]) -> bool {
-        positions
+    fn reset_counters(&mut self) {
+        let markings = &self.markings;
+        self.remaining = self
+            .win_lines
+            .lines
             .iter()
-            .all(|p| self.markings[p.to_idx()].is_marked())
+            .map(|line| {
+                line.iter()
+                    .filter(|&&idx| !markings[idx].is_marked())
+                    .count()
+            })
+            .collect();
+        self.winning_line = self.remaining.iter().position(|&count| count == 0);
     }
 
-    fn is_winner(&self) -> bool {
-        for i in 0..5 {
-            let horizontal_positions: Vec<Position> = (0..5).map(|j| Position::new(i, j)).collect();
-            if self.has_marked_all(&horizontal_positions) {
-                return true;
+    fn to_idx(&self, position: &Position) -> usize {
+        assert!(position.row < self.rows);
+        assert!(position.col < self.cols);
+        position.row * self.cols + position.col
+    }
+
+    fn position(&self, idx: usize) -> Position {
+        Position::new(idx / self.cols, idx % self.cols)
+    }
+
+    // Returns whether marking this cell is what made the board win.
+    fn mark_cell(&mut self, idx: usize) -> bool {
+        if self.markings[idx].is_marked() {
+            return false;
+        }
+        self.markings[idx] = Marking::Marked;
+        let was_winner = self.is_winner();
+        for &line_idx in &self.win_lines.cell_lines[idx] {
+            self.remaining[line_idx] -= 1;
+            if self.remaining[line_idx] == 0 && self.winning_line.is_none() {
+                self.winning_line = Some(line_idx);
             }
-            let vertical_positions: Vec<Position> = (0..5).map(|j| Position::new(j, i)).collect();
-            if self.has_marked_all(&vertical_positions) {
-                return true;
+        }
+        !was_winner && self.is_winner()
+    }
+
+    fn unmark_cell(&mut self, idx: usize) {
+        if !self.markings[idx].is_marked() {
+            return;
+        }
+        self.markings[idx] = Marking::Unmarked;
+        for &line_idx in &self.win_lines.cell_lines[idx] {
+            self.remaining[line_idx] += 1;
+        }
+        if let Some(line_idx) = self.winning_line {
+            if self.remaining[line_idx] > 0 {
+                self.winning_line = self.remaining.iter().position(|&count| count == 0);
             }
         }
-        false
+    }
+
+    fn is_winner(&self) -> bool {
+        self.winning_line.is_some()
+    }
+
+    // The first line that got completed.
+    fn winning_positions(&self) -> Option<Vec<Position>> {
+        let line = &self.win_lines.lines[self.winning_line?];
+        Some(line.iter().map(|&idx| self.position(idx)).collect())
+    }
+
+    // The values that have to be drawn to complete each of the win lines.
+    fn line_values(&self) -> Vec<Vec<u32>> {
+        self.win_lines
+            .lines
+            .iter()
+            .map(|line| {
+                let mut values: Vec<u32> = line.iter().map(|&idx| self.values[idx]).collect();
+                values.sort_unstable();
+                values.dedup();
+                values
+            })
+            .collect()
     }
 
     fn sum_of_unmarked(&self) -> u32 {
         let mut sum = 0;
-        for idx in 0..25 {
+        for idx in 0..self.values.len() {
             if let Marking::Unmarked = self.markings[idx] {
                 sum += self.values[idx]
             }
@@ -88,71 +296,688 @@ impl Board {
     }
 }
 
-fn main() {
-    println!("{}", part1());
-    println!("{}", part2());
+// Makes every board play by the same set of win conditions.
+fn apply_win_conditions(
+    boards: &mut [Board],
+    conditions: &[WinCondition],
+) -> Result<(), WinConditionError> {
+    if let Some(first) = boards.first() {
+        let win_lines = Rc::new(WinLines::new(first.rows, first.cols, conditions)?);
+        for board in boards.iter_mut() {
+            board.set_win_lines(Rc::clone(&win_lines));
+        }
+    }
+    Ok(())
 }
 
-fn read_input() -> (Vec<u32>, Vec<Board>) {
-    let input = fs::read_to_string("data/day_04.txt").expect("File missing");
-    let mut lines = input.lines();
+// Plays a set of boards, finding the cells to mark for a drawn number through an index instead of
+// scanning every board.
+struct Game {
+    boards: Vec<Board>,
+    cells_by_value: HashMap<u32, Vec<(usize, usize)>>,
+    num_winners: usize,
+    history: Vec<Draw>,
+}
 
-    let numbers = lines.next().unwrap();
-    let numbers: Vec<u32> = numbers
-        .split(',')
-        .map(|s| s.trim().parse().unwrap())
-        .collect();
+// What a single draw changed, so it can be taken back.
+struct Draw {
+    number: u32,
+    marked: Vec<(usize, usize)>,
+    winners: Vec<usize>,
+}
 
-    let board_numbers: Vec<u32> = lines
-        .filter(|&s| !s.trim().is_empty())
-        .join(" ")
-        .split_whitespace()
-        .map(|s| s.parse().unwrap())
-        .collect();
+impl Game {
+    fn new(boards: Vec<Board>) -> Self {
+        let mut cells_by_value: HashMap<u32, Vec<(usize, usize)>> = HashMap::new();
+        for (board_idx, board) in boards.iter().enumerate() {
+            for (cell_idx, &value) in board.values.iter().enumerate() {
+                cells_by_value
+                    .entry(value)
+                    .or_default()
+                    .push((board_idx, cell_idx));
+            }
+        }
+        let num_winners = boards.iter().filter(|board| board.is_winner()).count();
+        Self {
+            boards,
+            cells_by_value,
+            num_winners,
+            history: Vec::new(),
+        }
+    }
 
-    let boards = board_numbers
-        .chunks(25)
-        .map(|chunk| Board::new(chunk))
+    // Marks `number` everywhere and returns the boards that won because of it, in board order.
+    fn draw(&mut self, number: u32) -> Vec<usize> {
+        let mut marked = Vec::new();
+        let mut new_winners = Vec::new();
+        if let Some(cells) = self.cells_by_value.get(&number) {
+            for &(board_idx, cell_idx) in cells {
+                let board = &mut self.boards[board_idx];
+                if board.markings[cell_idx].is_marked() {
+                    continue;
+                }
+                marked.push((board_idx, cell_idx));
+                if board.mark_cell(cell_idx) {
+                    new_winners.push(board_idx);
+                }
+            }
+        }
+        self.num_winners += new_winners.len();
+        self.history.push(Draw {
+            number,
+            marked,
+            winners: new_winners.clone(),
+        });
+        new_winners
+    }
+
+    // Takes back the most recent draw, returning the number that was drawn.
+    fn undo(&mut self) -> Option<u32> {
+        let draw = self.history.pop()?;
+        for &(board_idx, cell_idx) in draw.marked.iter().rev() {
+            self.boards[board_idx].unmark_cell(cell_idx);
+        }
+        self.num_winners -= draw.winners.len();
+        Some(draw.number)
+    }
+
+    fn all_won(&self) -> bool {
+        self.num_winners == self.boards.len()
+    }
+
+    // Draws `numbers` in order until every board has won or the numbers run out.
+    fn play(mut self, numbers: &[u32]) -> Timeline {
+        let mut wins = Vec::new();
+        for (draw_idx, &number) in numbers.iter().enumerate() {
+            if self.all_won() {
+                break;
+            }
+            for board_idx in self.draw(number) {
+                let board = &self.boards[board_idx];
+                wins.push(Win {
+                    board: board_idx,
+                    number,
+                    draw_idx,
+                    line: board.winning_positions().unwrap(),
+                    score: board.sum_of_unmarked() * number,
+                });
+            }
+        }
+        let never_won = (0..self.boards.len())
+            .filter(|&board_idx| !self.boards[board_idx].is_winner())
+            .collect();
+        Timeline { wins, never_won }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Win {
+    board: usize,
+    number: u32,
+    draw_idx: usize,
+    line: Vec<Position>,
+    score: u32,
+}
+
+// Every board that won in the order they won, boards winning on the same draw ordered by index.
+#[derive(Debug)]
+struct Timeline {
+    wins: Vec<Win>,
+    never_won: Vec<usize>,
+}
+
+impl Timeline {
+    fn first(&self) -> Option<&Win> {
+        self.wins.first()
+    }
+
+    fn last(&self) -> Option<&Win> {
+        self.wins.last()
+    }
+
+    fn rank(&self, board: usize) -> Option<usize> {
+        self.wins.iter().position(|win| win.board == board)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RigGoal {
+    WinFirst,
+    WinLast,
+}
+
+#[derive(Debug, PartialEq)]
+enum RigError {
+    UnknownBoard,
+    Impossible,
+}
+
+// Finds a permutation of some of the `available` numbers so that fresh `boards` play out with
+// `target` winning first or last on its own, i.e. without another board winning on the same draw.
+// For `WinFirst` the sequence is as short as possible; `Impossible` means no sequence of draws
+// does it.
+fn rig(
+    boards: &[Board],
+    available: &[u32],
+    target: usize,
+    goal: RigGoal,
+) -> Result<Vec<u32>, RigError> {
+    if target >= boards.len() {
+        return Err(RigError::UnknownBoard);
+    }
+    // Lines with a value that's never drawn can't be completed, so they might as well not exist.
+    let available: HashSet<u32> = available.iter().copied().collect();
+    let lines: Vec<Vec<Vec<u32>>> = boards
+        .iter()
+        .map(|board| {
+            board
+                .line_values()
+                .into_iter()
+                .filter(|line| is_subset(line, &available))
+                .collect()
+        })
         .collect();
+    let draws = match goal {
+        RigGoal::WinFirst => rig_first(&lines, target),
+        RigGoal::WinLast => rig_last(&lines, target),
+    }
+    .ok_or(RigError::Impossible)?;
+    assert!(
+        is_subset(&draws, &available) && is_rigged(boards, target, goal, &draws),
+        "rigged draws don't replay as planned"
+    );
+    Ok(draws)
+}
 
-    (numbers, boards)
+fn is_rigged(boards: &[Board], target: usize, goal: RigGoal, draws: &[u32]) -> bool {
+    let timeline = Game::new(boards.to_vec()).play(draws);
+    let win = match goal {
+        RigGoal::WinFirst => timeline.first(),
+        RigGoal::WinLast if timeline.never_won.is_empty() => timeline.last(),
+        RigGoal::WinLast => None,
+    };
+    match win {
+        Some(win) if win.board == target => {
+            timeline
+                .wins
+                .iter()
+                .filter(|other| other.draw_idx == win.draw_idx)
+                .count()
+                == 1
+        }
+        _ => false,
+    }
 }
 
-fn part1() -> u32 {
-    let (numbers, mut boards) = read_input();
-    for number in numbers {
-        for board in boards.iter_mut() {
-            board.mark(number);
-            if board.is_winner() {
-                return board.sum_of_unmarked() * number;
+fn is_subset(values: &[u32], drawn: &HashSet<u32>) -> bool {
+    values.iter().all(|value| drawn.contains(value))
+}
+
+// Any winning sequence has to draw a whole line of the target, so the shortest one is the
+// smallest target line that doesn't also complete a line on another board.
+fn rig_first(lines: &[Vec<Vec<u32>>], target: usize) -> Option<Vec<u32>> {
+    lines[target]
+        .iter()
+        .filter(|line| {
+            let drawn: HashSet<u32> = line.iter().copied().collect();
+            lines
+                .iter()
+                .enumerate()
+                .filter(|&(board_idx, _)| board_idx != target)
+                .all(|(_, board_lines)| !board_lines.iter().any(|l| is_subset(l, &drawn)))
+        })
+        .min_by_key(|line| line.len())
+        .cloned()
+}
+
+// Completes one line on every other board without completing any line of the target, then
+// finishes a target line.  Only the values shared with the target matter for that, so each board
+// is reduced to the distinct target values its lines would draw and searched depth first.
+fn rig_last(lines: &[Vec<Vec<u32>>], target: usize) -> Option<Vec<u32>> {
+    let target_values: HashSet<u32> = lines[target].iter().flatten().copied().collect();
+    let shared = |line: &Vec<u32>| -> BTreeSet<u32> {
+        line.iter()
+            .copied()
+            .filter(|value| target_values.contains(value))
+            .collect()
+    };
+
+    let mut boards: Vec<Vec<(BTreeSet<u32>, &Vec<u32>)>> = Vec::new();
+    for (board_idx, board_lines) in lines.iter().enumerate() {
+        if board_idx == target {
+            continue;
+        }
+        let mut options: Vec<(BTreeSet<u32>, &Vec<u32>)> = Vec::new();
+        let mut candidates: Vec<&Vec<u32>> = board_lines.iter().collect();
+        candidates.sort_by_key(|line| (shared(line).len(), line.len()));
+        for line in candidates {
+            let line_shared = shared(line);
+            if !options
+                .iter()
+                .any(|(option, _)| option.is_subset(&line_shared))
+            {
+                options.push((line_shared, line));
+            }
+        }
+        boards.push(options);
+    }
+    boards.sort_by_key(|options| options.len());
+
+    let completes_target = |drawn: &BTreeSet<u32>| {
+        lines[target]
+            .iter()
+            .any(|line| line.iter().all(|value| drawn.contains(value)))
+    };
+
+    fn search<'a>(
+        boards: &[Vec<(BTreeSet<u32>, &'a Vec<u32>)>],
+        board_idx: usize,
+        drawn: &BTreeSet<u32>,
+        chosen: &mut Vec<&'a Vec<u32>>,
+        failed: &mut HashSet<(usize, BTreeSet<u32>)>,
+        completes_target: &dyn Fn(&BTreeSet<u32>) -> bool,
+    ) -> bool {
+        if board_idx == boards.len() {
+            return true;
+        }
+        if failed.contains(&(board_idx, drawn.clone())) {
+            return false;
+        }
+        let mut options: Vec<&(BTreeSet<u32>, &Vec<u32>)> = boards[board_idx].iter().collect();
+        options.sort_by_key(|(option, _)| option.difference(drawn).count());
+        for (option, line) in options {
+            let next: BTreeSet<u32> = drawn.union(option).copied().collect();
+            if completes_target(&next) {
+                continue;
+            }
+            chosen.push(line);
+            if search(
+                boards,
+                board_idx + 1,
+                &next,
+                chosen,
+                failed,
+                completes_target,
+            ) {
+                return true;
             }
+            chosen.pop();
         }
+        failed.insert((board_idx, drawn.clone()));
+        false
     }
-    panic!("No solution found");
+
+    let mut chosen = Vec::new();
+    let mut failed = HashSet::new();
+    if !search(
+        &boards,
+        0,
+        &BTreeSet::new(),
+        &mut chosen,
+        &mut failed,
+        &completes_target,
+    ) {
+        return None;
+    }
+
+    let mut draws: Vec<u32> = Vec::new();
+    let mut drawn = HashSet::new();
+    for line in chosen {
+        draws.extend(line.iter().filter(|&&value| drawn.insert(value)));
+    }
+    let finish = lines[target]
+        .iter()
+        .min_by_key(|line| line.iter().filter(|value| !drawn.contains(value)).count())?;
+    draws.extend(finish.iter().filter(|&&value| drawn.insert(value)));
+    Some(draws)
 }
 
-fn part2() -> u32 {
-    let (numbers, mut boards) = read_input();
-    for number in numbers {
-        if boards.len() == 1 {
-            let board = boards.first_mut().unwrap();
-            board.mark(number);
-            if board.is_winner() {
-                return board.sum_of_unmarked() * number;
+// Plays a game one command per line.  Every reply ends with a line starting with `ok` or `error`:
+//
+//   draw <number>  marks the number, replying `marked <boards>` and `winner <board> <score>` lines
+//   show <board>   prints the board, marked cells followed by `*`
+//   undo           takes back the last draw, replying `undone <number>`
+//   quit           ends the session
+struct Session {
+    game: Game,
+}
+
+impl Session {
+    fn new(boards: Vec<Board>) -> Self {
+        Self {
+            game: Game::new(boards),
+        }
+    }
+
+    // Returns the reply lines and whether to keep going.
+    fn handle(&mut self, command: &str) -> (Vec<String>, bool) {
+        let mut words = command.split_whitespace();
+        let reply = match (words.next(), words.next(), words.next()) {
+            (Some("draw"), Some(number), None) => match number.parse() {
+                Ok(number) => self.draw(number),
+                Err(_) => vec![format!("error {:?} is not a number", number)],
+            },
+            (Some("show"), Some(board), None) => match board.parse::<usize>() {
+                Ok(board_idx) if board_idx < self.game.boards.len() => self.show(board_idx),
+                _ => vec![format!("error no board {:?}", board)],
+            },
+            (Some("undo"), None, None) => match self.game.undo() {
+                Some(number) => vec![format!("undone {}", number), "ok".to_string()],
+                None => vec!["error nothing to undo".to_string()],
+            },
+            (Some("quit"), None, None) => return (vec!["ok bye".to_string()], false),
+            _ => vec![format!("error unknown command {:?}", command.trim())],
+        };
+        (reply, true)
+    }
+
+    fn draw(&mut self, number: u32) -> Vec<String> {
+        let winners = self.game.draw(number);
+        let draw = self.game.history.last().unwrap();
+        let mut boards: Vec<usize> = draw
+            .marked
+            .iter()
+            .map(|&(board_idx, _)| board_idx)
+            .collect();
+        boards.dedup();
+
+        let mut reply = vec![boards.iter().fold("marked".to_string(), |line, board_idx| {
+            format!("{} {}", line, board_idx)
+        })];
+        for board_idx in winners {
+            let score = self.game.boards[board_idx].sum_of_unmarked() * number;
+            reply.push(format!("winner {} {}", board_idx, score));
+        }
+        reply.push("ok".to_string());
+        reply
+    }
+
+    fn show(&self, board_idx: usize) -> Vec<String> {
+        let board = &self.game.boards[board_idx];
+        let mut reply: Vec<String> = (0..board.rows)
+            .map(|row| {
+                (0..board.cols)
+                    .map(|col| {
+                        let idx = board.to_idx(&Position::new(row, col));
+                        let mark = if board.markings[idx].is_marked() {
+                            "*"
+                        } else {
+                            " "
+                        };
+                        format!("{:>3}{}", board.values[idx], mark)
+                    })
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect();
+        reply.push("ok".to_string());
+        reply
+    }
+
+    fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (reply, keep_going) = self.handle(&line);
+            for reply_line in reply {
+                writeln!(output, "{}", reply_line)?;
+            }
+            output.flush()?;
+            if !keep_going {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Serves one session at a time, each starting from fresh boards.  A connection failing, e.g. the
+// client hanging up mid-reply, only ends its own session.
+fn serve(listener: TcpListener, boards: &[Board]) {
+    for stream in listener.incoming() {
+        if let Err(err) = stream.and_then(|stream| serve_session(stream, boards)) {
+            eprintln!("session ended: {}", err);
+        }
+    }
+}
+
+fn serve_session(stream: TcpStream, boards: &[Board]) -> io::Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    Session::new(boards.to_vec()).run(reader, stream)
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    MissingNumbers,
+    InvalidNumber(String),
+    NoBoards,
+    RaggedBoard {
+        board: usize,
+        row: usize,
+    },
+    InconsistentBoard {
+        board: usize,
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingNumbers => write!(f, "missing the line of drawn numbers"),
+            ParseError::InvalidNumber(s) => write!(f, "{:?} is not a number", s),
+            ParseError::NoBoards => write!(f, "no boards"),
+            ParseError::RaggedBoard { board, row } => write!(
+                f,
+                "row {} of board {} has a different length than the rows above it",
+                row, board
+            ),
+            ParseError::InconsistentBoard {
+                board,
+                expected,
+                found,
+            } => write!(
+                f,
+                "board {} is {}x{}, expected {}x{} like the first board",
+                board, found.0, found.1, expected.0, expected.1
+            ),
+        }
+    }
+}
+
+fn parse_number(s: &str) -> Result<u32, ParseError> {
+    s.trim()
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber(s.trim().to_string()))
+}
+
+// The first line holds the drawn numbers, followed by blank line separated boards.  The size of
+// the boards is taken from the first one and every board has to be a rectangle of that size.
+fn parse_game(input: &str) -> Result<(Vec<u32>, Vec<Board>), ParseError> {
+    let mut lines = input.lines();
+
+    let numbers = lines.next().ok_or(ParseError::MissingNumbers)?;
+    if numbers.trim().is_empty() {
+        return Err(ParseError::MissingNumbers);
+    }
+    let numbers = numbers
+        .split(',')
+        .map(parse_number)
+        .collect::<Result<Vec<u32>, ParseError>>()?;
+
+    let mut blocks: Vec<Vec<&str>> = Vec::new();
+    let mut block = Vec::new();
+    for line in lines {
+        if line.trim().is_empty() {
+            if !block.is_empty() {
+                blocks.push(block);
+                block = Vec::new();
             }
         } else {
-            for board in boards.iter_mut() {
-                board.mark(number);
+            block.push(line);
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(block);
+    }
+
+    let mut boards: Vec<Board> = Vec::new();
+    for (board_idx, block) in blocks.into_iter().enumerate() {
+        let mut values = Vec::new();
+        let mut cols = 0;
+        for (row, line) in block.iter().enumerate() {
+            let row_values = line
+                .split_whitespace()
+                .map(parse_number)
+                .collect::<Result<Vec<u32>, ParseError>>()?;
+            if row == 0 {
+                cols = row_values.len();
+            } else if row_values.len() != cols {
+                return Err(ParseError::RaggedBoard {
+                    board: board_idx,
+                    row,
+                });
+            }
+            values.extend(row_values);
+        }
+
+        let rows = block.len();
+        if let Some(first) = boards.first() {
+            if (first.rows, first.cols) != (rows, cols) {
+                return Err(ParseError::InconsistentBoard {
+                    board: board_idx,
+                    expected: (first.rows, first.cols),
+                    found: (rows, cols),
+                });
+            }
+        }
+        boards.push(Board::new(rows, cols, &values));
+    }
+
+    if boards.is_empty() {
+        return Err(ParseError::NoBoards);
+    }
+    Ok((numbers, boards))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("--interactive") => {
+            let (_, boards) = read_input();
+            let stdin = io::stdin();
+            Session::new(boards)
+                .run(stdin.lock(), io::stdout())
+                .expect("I/O error");
+        }
+        Some("--listen") => {
+            let (_, boards) = read_input();
+            let address = args.get(2).map_or("127.0.0.1:4004", String::as_str);
+            let listener = TcpListener::bind(address).expect("Can't listen");
+            serve(listener, &boards);
+        }
+        // --win rows,diagonals,#...#/.#.#./..#../.#.#./#...#
+        Some("--win") => {
+            let conditions: Vec<WinCondition> = args
+                .get(2)
+                .expect("Expected --win <conditions>")
+                .split(',')
+                .map(WinCondition::from_arg)
+                .collect();
+            let (numbers, mut boards) = read_input();
+            apply_win_conditions(&mut boards, &conditions).unwrap_or_else(|err| panic!("{}", err));
+            let timeline = Game::new(boards).play(&numbers);
+            for win in &timeline.wins {
+                println!(
+                    "board {} wins on draw {} with score {}",
+                    win.board, win.draw_idx, win.score
+                );
+            }
+            println!("{} boards never win", timeline.never_won.len());
+        }
+        Some("--rank") => {
+            let board = args
+                .get(2)
+                .and_then(|arg| arg.parse().ok())
+                .expect("Expected --rank <board>");
+            let (numbers, boards) = read_input();
+            match Game::new(boards).play(&numbers).rank(board) {
+                Some(rank) => println!("board {} wins in place {}", board, rank + 1),
+                None => println!("board {} never wins", board),
             }
-            boards.retain(|board| !board.is_winner());
+        }
+        // --rig <board> first|last
+        Some("--rig") => {
+            let board = args
+                .get(2)
+                .and_then(|arg| arg.parse().ok())
+                .expect("Expected --rig <board> first|last");
+            let goal = match args.get(3).map(String::as_str) {
+                Some("first") => RigGoal::WinFirst,
+                Some("last") => RigGoal::WinLast,
+                _ => panic!("Expected --rig <board> first|last"),
+            };
+            let (numbers, boards) = read_input();
+            match rig(&boards, &numbers, board, goal) {
+                Ok(draws) => {
+                    let draws: Vec<String> = draws.iter().map(u32::to_string).collect();
+                    println!("{}", draws.join(","));
+                }
+                Err(err) => println!("{:?}", err),
+            }
+        }
+        _ => {
+            println!("{}", part1());
+            println!("{}", part2());
         }
     }
-    panic!("No solution found");
+}
+
+fn read_input() -> (Vec<u32>, Vec<Board>) {
+    let input = fs::read_to_string("data/day_04.txt").expect("File missing");
+    parse_game(&input).unwrap()
+}
+
+fn part1() -> u32 {
+    let (numbers, boards) = read_input();
+    let timeline = Game::new(boards).play(&numbers);
+    timeline.first().expect("No solution found").score
+}
+
+fn part2() -> u32 {
+    let (numbers, boards) = read_input();
+    let timeline = Game::new(boards).play(&numbers);
+    assert!(timeline.never_won.is_empty(), "No solution found");
+    timeline.last().unwrap().score
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    // Marks `value` by scanning every cell, the reference `Game` is checked against.
+    fn mark(board: &mut Board, value: u32) {
+        for idx in 0..board.values.len() {
+            if board.values[idx] == value {
+                board.mark_cell(idx);
+            }
+        }
+    }
+
+    const SMALL_GAME: &str = "1,4,9,7,2,8,5\n\
+                              \n\
+                              1 2 3\n\
+                              4 5 6\n\
+                              7 8 9\n\
+                              \n\
+                              10 2 11\n\
+                              12 5 13\n\
+                              14 8 15\n";
 
     #[test]
     fn test_part1() {
@@ -163,4 +988,381 @@ mod tests {
     fn test_part2() {
         assert_eq!(part2(), 12738);
     }
+
+    #[test]
+    fn test_small_boards() {
+        let (numbers, boards) = parse_game(SMALL_GAME).unwrap();
+        assert_eq!(boards.len(), 2);
+        assert_eq!((boards[0].rows, boards[0].cols), (3, 3));
+        let timeline = Game::new(boards).play(&numbers);
+        assert_eq!(
+            timeline.wins,
+            vec![
+                Win {
+                    board: 0,
+                    number: 7,
+                    draw_idx: 3,
+                    line: vec![
+                        Position::new(0, 0),
+                        Position::new(1, 0),
+                        Position::new(2, 0)
+                    ],
+                    score: 7 * (2 + 3 + 5 + 6 + 8),
+                },
+                Win {
+                    board: 1,
+                    number: 5,
+                    draw_idx: 6,
+                    line: vec![
+                        Position::new(0, 1),
+                        Position::new(1, 1),
+                        Position::new(2, 1)
+                    ],
+                    score: 5 * (10 + 11 + 12 + 13 + 14 + 15),
+                }
+            ]
+        );
+        assert!(timeline.never_won.is_empty());
+        assert_eq!(timeline.rank(1), Some(1));
+
+        let (numbers, boards) = parse_game(SMALL_GAME).unwrap();
+        let timeline = Game::new(boards).play(&numbers[..6]);
+        assert_eq!(timeline.wins.len(), 1);
+        assert_eq!(timeline.never_won, vec![1]);
+        assert_eq!(timeline.rank(1), None);
+    }
+
+    #[test]
+    fn test_non_square_boards() {
+        let (numbers, boards) = parse_game("3,4,1\n\n1 2\n3 4\n5 6\n").unwrap();
+        assert_eq!((boards[0].rows, boards[0].cols), (3, 2));
+        let timeline = Game::new(boards).play(&numbers);
+        assert_eq!(timeline.first().unwrap().score, 4 * (1 + 2 + 5 + 6));
+
+        let mut board = Board::new(2, 10, &(0..20).collect::<Vec<u32>>());
+        for value in 0..9 {
+            mark(&mut board, value);
+        }
+        assert!(!board.is_winner());
+        mark(&mut board, 9);
+        assert!(board.is_winner());
+    }
+
+    fn board_3x3() -> Board {
+        Board::new(3, 3, &[1, 2, 3, 4, 5, 6, 7, 8, 9])
+    }
+
+    fn won_with(conditions: &[WinCondition], marks: &[u32]) -> Option<Vec<Position>> {
+        let mut boards = vec![board_3x3()];
+        apply_win_conditions(&mut boards, conditions).unwrap();
+        for &value in marks {
+            mark(&mut boards[0], value);
+        }
+        boards[0].winning_positions()
+    }
+
+    #[test]
+    fn test_win_conditions() {
+        assert_eq!(won_with(&[WinCondition::Rows], &[1, 4, 7]), None);
+        assert_eq!(
+            won_with(&[WinCondition::Columns], &[1, 4, 7]),
+            Some(vec![
+                Position::new(0, 0),
+                Position::new(1, 0),
+                Position::new(2, 0)
+            ])
+        );
+        assert_eq!(
+            won_with(&[WinCondition::Diagonals], &[7, 5, 3]),
+            Some(vec![
+                Position::new(0, 2),
+                Position::new(1, 1),
+                Position::new(2, 0)
+            ])
+        );
+        assert_eq!(won_with(&[WinCondition::FourCorners], &[1, 3, 7]), None);
+        assert!(won_with(&[WinCondition::FourCorners], &[1, 3, 7, 9]).is_some());
+        assert_eq!(
+            won_with(&[WinCondition::Blackout], &[1, 2, 3, 4, 5, 6, 7, 8]),
+            None
+        );
+        assert!(won_with(&[WinCondition::Blackout], &[1, 2, 3, 4, 5, 6, 7, 8, 9]).is_some());
+
+        let plus = || WinCondition::pattern(".#.\n###\n.#.");
+        assert_eq!(won_with(&[plus()], &[2, 4, 5, 6]), None);
+        assert_eq!(
+            won_with(&[plus()], &[2, 4, 5, 6, 8]),
+            Some(vec![
+                Position::new(0, 1),
+                Position::new(1, 0),
+                Position::new(1, 1),
+                Position::new(1, 2),
+                Position::new(2, 1)
+            ])
+        );
+        // The line that got completed first is the one that won.
+        assert_eq!(
+            won_with(&[plus(), WinCondition::Rows], &[2, 4, 5, 6, 8]),
+            Some(vec![
+                Position::new(1, 0),
+                Position::new(1, 1),
+                Position::new(1, 2)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_win_counters() {
+        let mut board = board_3x3();
+        mark(&mut board, 1);
+        mark(&mut board, 1);
+        mark(&mut board, 2);
+        assert!(!board.is_winner());
+        assert_eq!(board.remaining, vec![1, 3, 3, 2, 2, 3]);
+
+        // Switching rules keeps the marks made so far.
+        board.set_win_lines(Rc::new(
+            WinLines::new(3, 3, &[WinCondition::pattern("##.\n...\n...")]).unwrap(),
+        ));
+        assert!(board.is_winner());
+    }
+
+    #[test]
+    fn test_win_condition_errors() {
+        let mut boards = vec![Board::new(2, 3, &[1, 2, 3, 4, 5, 6])];
+        assert_eq!(
+            apply_win_conditions(&mut boards, &[WinCondition::Diagonals]),
+            Err(WinConditionError::NotSquare { rows: 2, cols: 3 })
+        );
+        assert_eq!(
+            apply_win_conditions(&mut boards, &[WinCondition::pattern("##\n##")]),
+            Err(WinConditionError::PatternSize {
+                expected: 6,
+                found: 4
+            })
+        );
+        assert_eq!(
+            apply_win_conditions(&mut boards, &[WinCondition::pattern("...\n...")]),
+            Err(WinConditionError::EmptyPattern)
+        );
+    }
+
+    #[test]
+    fn test_game_matches_scanning_boards() {
+        let mut rng = StdRng::seed_from_u64(35);
+        let mut values: Vec<u32> = (0..2_000).collect();
+        let boards: Vec<Board> = (0..1_000)
+            .map(|_| {
+                values.shuffle(&mut rng);
+                Board::new(5, 5, &values[..25])
+            })
+            .collect();
+        values.shuffle(&mut rng);
+
+        let mut game = Game::new(boards.clone());
+        let mut scanned = boards;
+        for &number in &values {
+            let winners = game.draw(number);
+            let mut expected = Vec::new();
+            for (board_idx, board) in scanned.iter_mut().enumerate() {
+                let was_winner = board.is_winner();
+                mark(board, number);
+                if !was_winner && board.is_winner() {
+                    expected.push(board_idx);
+                }
+            }
+            assert_eq!(winners, expected);
+        }
+        assert!(game.all_won());
+    }
+
+    #[test]
+    fn test_rig() {
+        let (_, boards) = parse_game(SMALL_GAME).unwrap();
+        let all: Vec<u32> = (1..=15).collect();
+        // The middle column of the second board would complete one on the first board as well.
+        assert_eq!(
+            rig(&boards, &all, 1, RigGoal::WinFirst),
+            Ok(vec![2, 10, 11])
+        );
+        assert_eq!(rig(&boards, &all, 0, RigGoal::WinFirst), Ok(vec![1, 2, 3]));
+        // Draw the first board's first column, which has nothing in common with the second board.
+        assert_eq!(
+            rig(&boards, &all, 1, RigGoal::WinLast),
+            Ok(vec![1, 4, 7, 2, 10, 11])
+        );
+        assert_eq!(
+            rig(&boards, &all, 2, RigGoal::WinLast),
+            Err(RigError::UnknownBoard)
+        );
+
+        let twins = vec![board_3x3(), board_3x3()];
+        assert_eq!(
+            rig(&twins, &all, 0, RigGoal::WinFirst),
+            Err(RigError::Impossible)
+        );
+        assert_eq!(
+            rig(&twins, &all, 1, RigGoal::WinLast),
+            Err(RigError::Impossible)
+        );
+    }
+
+    #[test]
+    fn test_rig_available_numbers() {
+        let (numbers, boards) = parse_game(SMALL_GAME).unwrap();
+        // 3 and 6 are never drawn, and the second board's only completable line is its middle
+        // column, which completes the first board's middle column too.
+        assert_eq!(
+            rig(&boards, &numbers, 0, RigGoal::WinFirst),
+            Ok(vec![7, 8, 9])
+        );
+        assert_eq!(
+            rig(&boards, &numbers, 1, RigGoal::WinFirst),
+            Err(RigError::Impossible)
+        );
+        assert_eq!(
+            rig(&boards, &numbers, 0, RigGoal::WinLast),
+            Err(RigError::Impossible)
+        );
+        assert_eq!(
+            rig(&boards, &numbers, 1, RigGoal::WinLast),
+            Ok(vec![1, 4, 7, 2, 5, 8])
+        );
+        assert_eq!(
+            rig(&boards, &[1, 2, 3], 1, RigGoal::WinLast),
+            Err(RigError::Impossible)
+        );
+    }
+
+    #[test]
+    fn test_rig_input() {
+        let (numbers, boards) = read_input();
+        for &target in &[0, 42, boards.len() - 1] {
+            for &goal in &[RigGoal::WinFirst, RigGoal::WinLast] {
+                let draws = rig(&boards, &numbers, target, goal).unwrap();
+                assert!(draws.iter().all(|draw| numbers.contains(draw)));
+                assert!(is_rigged(&boards, target, goal, &draws));
+            }
+        }
+    }
+
+    fn replies(session: &mut Session, commands: &str) -> String {
+        let mut output = Vec::new();
+        session.run(commands.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_session() {
+        let (_, boards) = parse_game(SMALL_GAME).unwrap();
+        let mut session = Session::new(boards);
+        assert_eq!(
+            replies(&mut session, "draw 2\ndraw 3\ndraw 99\nshow 0\n"),
+            "marked 0 1\nok\nmarked 0\nok\nmarked\nok\n  1   2*  3*\n  4   5   6\n  7   8   9\nok\n"
+        );
+        assert_eq!(
+            replies(&mut session, "draw 5\ndraw 8\n"),
+            "marked 0 1\nok\nmarked 0 1\nwinner 0 216\nwinner 1 600\nok\n"
+        );
+        assert_eq!(
+            replies(&mut session, "undo\nundo\ndraw 8\n"),
+            "undone 8\nok\nundone 5\nok\nmarked 0 1\nok\n"
+        );
+        assert!(session
+            .game
+            .history
+            .iter()
+            .all(|draw| draw.winners.is_empty()));
+        assert_eq!(
+            replies(&mut session, "show 2\ndraw x\nfly\nquit\ndraw 5\n"),
+            "error no board \"2\"\nerror \"x\" is not a number\nerror unknown command \"fly\"\nok bye\n"
+        );
+        assert!(!session.game.boards[0].markings[4].is_marked());
+    }
+
+    #[test]
+    fn test_session_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (_, boards) = parse_game(SMALL_GAME).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            Session::new(boards).run(reader, stream).unwrap();
+        });
+
+        play_small_game(TcpStream::connect(address).unwrap());
+        server.join().unwrap();
+    }
+
+    fn play_small_game(mut client: TcpStream) {
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let mut reply = || {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_string();
+                let done = line.starts_with("ok") || line.starts_with("error");
+                lines.push(line);
+                if done {
+                    return lines;
+                }
+            }
+        };
+        for number in &[1, 4, 9] {
+            writeln!(client, "draw {}", number).unwrap();
+            assert_eq!(reply().last().unwrap(), "ok");
+        }
+        writeln!(client, "draw 7").unwrap();
+        assert_eq!(reply(), vec!["marked 0", "winner 0 168", "ok"]);
+        writeln!(client, "quit").unwrap();
+        assert_eq!(reply(), vec!["ok bye"]);
+    }
+
+    #[test]
+    fn test_serve_survives_broken_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // Serving never returns, so the thread is left running.
+        std::thread::spawn(move || {
+            let (_, boards) = parse_game(SMALL_GAME).unwrap();
+            serve(listener, &boards);
+        });
+
+        // Hangs up without reading any replies.
+        let mut client = TcpStream::connect(address).unwrap();
+        for _ in 0..100 {
+            writeln!(client, "show 0").unwrap();
+        }
+        drop(client);
+        // Sends a line that isn't UTF-8, which fails reading it.
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(b"draw 1\n\xff\n").unwrap();
+        drop(client);
+
+        play_small_game(TcpStream::connect(address).unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_game("").err(), Some(ParseError::MissingNumbers));
+        assert_eq!(parse_game("1,2\n\n").err(), Some(ParseError::NoBoards));
+        assert_eq!(
+            parse_game("1,x\n\n1 2\n3 4\n").err(),
+            Some(ParseError::InvalidNumber("x".to_string()))
+        );
+        assert_eq!(
+            parse_game("1,2\n\n1 2\n3 4 5\n").err(),
+            Some(ParseError::RaggedBoard { board: 0, row: 1 })
+        );
+        assert_eq!(
+            parse_game("1,2\n\n1 2\n3 4\n\n1 2 3\n4 5 6\n").err(),
+            Some(ParseError::InconsistentBoard {
+                board: 1,
+                expected: (2, 2),
+                found: (2, 3)
+            })
+        );
+    }
 }
@@ -1,6 +1,10 @@
-use std::{cmp::Ordering, collections::HashMap, fs};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+    env, fmt, fs,
+};
 
-#[derive(PartialEq, Eq, Hash, Debug)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 struct Point {
     x: i32,
     y: i32,
@@ -28,6 +32,58 @@ struct Line {
     b: Point,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rasterization {
+    // Only horizontal, vertical and 45 degree lines, which cover every point they pass through.
+    Strict,
+    // The points with integer coordinates that lie exactly on the line.
+    Lattice,
+    // A connected run of points approximating the line, one per step along its longer axis.
+    Bresenham,
+}
+
+#[derive(Debug, PartialEq)]
+enum LineError {
+    UnsupportedSlope { a: Point, b: Point },
+    UnsupportedSlope3 { a: Point3, b: Point3 },
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineError::UnsupportedSlope { a, b } => write!(
+                f,
+                "line {},{} -> {},{} is neither horizontal, vertical nor diagonal",
+                a.x, a.y, b.x, b.y
+            ),
+            LineError::UnsupportedSlope3 { a, b } => write!(
+                f,
+                "line {},{},{} -> {},{},{} is neither axis-aligned nor diagonal",
+                a.x, a.y, a.z, b.x, b.y, b.z
+            ),
+        }
+    }
+}
+
+impl Rasterization {
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "strict" => Some(Rasterization::Strict),
+            "lattice" => Some(Rasterization::Lattice),
+            "bresenham" => Some(Rasterization::Bresenham),
+            _ => None,
+        }
+    }
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
 impl Line {
     fn new(a: Point, b: Point) -> Self {
         Self { a, b }
@@ -37,7 +93,43 @@ impl Line {
         self.a.x != self.b.x && self.a.y != self.b.y
     }
 
-    fn points(&self) -> Vec<Point> {
+    // Whether the line is horizontal, vertical or at exactly 45 degrees.
+    fn is_strict(&self) -> bool {
+        !self.is_diagonal() || (self.a.x - self.b.x).abs() == (self.a.y - self.b.y).abs()
+    }
+
+    fn rasterize(&self, mode: Rasterization) -> Result<Vec<Point>, LineError> {
+        match mode {
+            Rasterization::Strict if !self.is_strict() => Err(LineError::UnsupportedSlope {
+                a: self.a,
+                b: self.b,
+            }),
+            Rasterization::Strict => Ok(self.strict_points()),
+            Rasterization::Lattice => Ok(self.lattice_points()),
+            Rasterization::Bresenham => Ok(self.bresenham_points()),
+        }
+    }
+
+    fn covers(&self, point: &Point, mode: Rasterization) -> bool {
+        let in_bounds = self.a.x.min(self.b.x) <= point.x
+            && point.x <= self.a.x.max(self.b.x)
+            && self.a.y.min(self.b.y) <= point.y
+            && point.y <= self.a.y.max(self.b.y);
+        if !in_bounds {
+            return false;
+        }
+        match mode {
+            // Strict lines cover every lattice point on them, so both come down to collinearity.
+            Rasterization::Strict | Rasterization::Lattice => {
+                let (dx, dy) = ((self.b.x - self.a.x) as i64, (self.b.y - self.a.y) as i64);
+                let (px, py) = ((point.x - self.a.x) as i64, (point.y - self.a.y) as i64);
+                dx * py == dy * px
+            }
+            Rasterization::Bresenham => self.bresenham_points().contains(point),
+        }
+    }
+
+    fn strict_points(&self) -> Vec<Point> {
         let dx = match self.a.x.cmp(&self.b.x) {
             Ordering::Equal => 0,
             Ordering::Greater => -1,
@@ -56,6 +148,42 @@ impl Line {
             .map(|n| Point::new(self.a.x + n * dx, self.a.y + n * dy))
             .collect()
     }
+
+    fn lattice_points(&self) -> Vec<Point> {
+        let (dx, dy) = (self.b.x - self.a.x, self.b.y - self.a.y);
+        let steps = gcd(dx.abs(), dy.abs());
+        if steps == 0 {
+            return vec![self.a];
+        }
+        // `steps` divides both deltas, and dividing first keeps long lines from overflowing.
+        let (step_x, step_y) = (dx / steps, dy / steps);
+        (0..=steps)
+            .map(|n| Point::new(self.a.x + n * step_x, self.a.y + n * step_y))
+            .collect()
+    }
+
+    fn bresenham_points(&self) -> Vec<Point> {
+        let dx = (self.b.x - self.a.x).abs();
+        let dy = -(self.b.y - self.a.y).abs();
+        let sx = (self.b.x - self.a.x).signum();
+        let sy = (self.b.y - self.a.y).signum();
+        let mut error = dx + dy;
+        let mut current = self.a;
+        let mut points = vec![current];
+        while current != self.b {
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                current.x += sx;
+            }
+            if doubled <= dx {
+                error += dx;
+                current.y += sy;
+            }
+            points.push(current);
+        }
+        points
+    }
 }
 
 impl From<&str> for Line {
@@ -68,47 +196,629 @@ impl From<&str> for Line {
     }
 }
 
-fn main() {
-    println!("{}", part1());
-    println!("{}", part2());
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+struct Point3 {
+    x: i32,
+    y: i32,
+    z: i32,
 }
 
-fn read_input() -> Vec<Line> {
-    let input = fs::read_to_string("data/day_05.txt").expect("File missing");
-    input.lines().map(|l| l.into()).collect()
+impl Point3 {
+    fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
 }
 
-fn part1() -> usize {
-    let lines = read_input();
-    let mut counter = HashMap::new();
+impl From<&str> for Point3 {
+    fn from(s: &str) -> Point3 {
+        let mut coordinates = s.split(',');
+        let mut next = || coordinates.next().unwrap().trim().parse().unwrap();
+        Point3::new(next(), next(), next())
+    }
+}
+
+#[derive(Debug)]
+struct Line3 {
+    a: Point3,
+    b: Point3,
+}
 
-    for line in lines.into_iter().filter(|line| !line.is_diagonal()) {
-        for point in line.points() {
-            let count = counter.entry(point).or_insert(0u32);
-            *count += 1
+impl Line3 {
+    fn new(a: Point3, b: Point3) -> Self {
+        Self { a, b }
+    }
+
+    fn deltas(&self) -> [i32; 3] {
+        [
+            self.b.x - self.a.x,
+            self.b.y - self.a.y,
+            self.b.z - self.a.z,
+        ]
+    }
+
+    // Axis-aligned lines and diagonals, which move by the same amount along every axis they move
+    // along at all.
+    fn is_supported(&self) -> bool {
+        let deltas = self.deltas();
+        let mut lengths = deltas.iter().map(|d| d.abs()).filter(|&d| d != 0);
+        match lengths.next() {
+            Some(length) => lengths.all(|d| d == length),
+            None => true,
         }
     }
 
-    counter.into_values().filter(|v| *v > 1).count()
+    fn points(&self) -> Result<Vec<Point3>, LineError> {
+        if !self.is_supported() {
+            return Err(LineError::UnsupportedSlope3 {
+                a: self.a,
+                b: self.b,
+            });
+        }
+        let [dx, dy, dz] = self.deltas();
+        let num_points = dx.abs().max(dy.abs()).max(dz.abs()) + 1;
+        Ok((0..num_points)
+            .map(|n| {
+                Point3::new(
+                    self.a.x + n * dx.signum(),
+                    self.a.y + n * dy.signum(),
+                    self.a.z + n * dz.signum(),
+                )
+            })
+            .collect())
+    }
 }
 
-fn part2() -> usize {
-    let lines = read_input();
-    let mut counter = HashMap::new();
+impl From<&str> for Line3 {
+    fn from(s: &str) -> Line3 {
+        let mut endpoints = s.split("->");
+        Line3::new(
+            endpoints.next().unwrap().into(),
+            endpoints.next().unwrap().into(),
+        )
+    }
+}
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Counting {
+    // A hash map entry per covered point.
+    Sparse,
+    // An array spanning the bounding box of all lines.
+    Dense,
+}
+
+fn count_overlaps_3d(lines: &[Line3], counting: Counting) -> Result<u64, LineError> {
+    let mut points = Vec::with_capacity(lines.len());
+    for line in lines {
+        points.push(line.points()?);
+    }
+    let all_points = || points.iter().flatten();
+
+    let overlapping = match counting {
+        Counting::Sparse => {
+            let mut counter = HashMap::new();
+            for point in all_points() {
+                *counter.entry(point).or_insert(0u32) += 1;
+            }
+            counter.values().filter(|&&v| v > 1).count()
+        }
+        Counting::Dense => {
+            let (min, max) = match all_points().next() {
+                Some(&first) => all_points().fold((first, first), |(min, max), p| {
+                    (
+                        Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                        Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                    )
+                }),
+                None => return Ok(0),
+            };
+            let size_x = (max.x - min.x) as usize + 1;
+            let size_y = (max.y - min.y) as usize + 1;
+            let size_z = (max.z - min.z) as usize + 1;
+            let volume = size_x
+                .checked_mul(size_y)
+                .and_then(|area| area.checked_mul(size_z))
+                .expect("bounding box too large for dense counting");
+            let mut counter = vec![0u32; volume];
+            for p in all_points() {
+                let idx = ((p.z - min.z) as usize * size_y + (p.y - min.y) as usize) * size_x
+                    + (p.x - min.x) as usize;
+                counter[idx] += 1;
+            }
+            counter.into_iter().filter(|&v| v > 1).count()
+        }
+    };
+    Ok(overlapping as u64)
+}
+
+// The cells a line covers on one row: a fixed range for horizontal lines, otherwise a single
+// cell moving `slope` columns per row.
+#[derive(Clone, Copy)]
+struct RowSpan {
+    y_start: i64,
+    y_end: i64,
+    x_start: i64,
+    x_end: i64,
+    slope: i64,
+}
+
+impl RowSpan {
+    fn new(line: &Line) -> Self {
+        assert!(line.is_strict(), "only strict lines can be swept");
+        let (top, bottom) = if line.a.y <= line.b.y {
+            (&line.a, &line.b)
+        } else {
+            (&line.b, &line.a)
+        };
+        let (y_start, y_end) = (top.y as i64, bottom.y as i64);
+        if y_start == y_end {
+            let (x_start, x_end) = (top.x.min(bottom.x) as i64, top.x.max(bottom.x) as i64);
+            Self {
+                y_start,
+                y_end,
+                x_start,
+                x_end,
+                slope: 0,
+            }
+        } else {
+            Self {
+                y_start,
+                y_end,
+                x_start: top.x as i64,
+                x_end: top.x as i64,
+                slope: (bottom.x as i64 - top.x as i64).signum(),
+            }
+        }
+    }
+
+    fn columns(&self, y: i64) -> (i64, i64) {
+        let offset = self.slope * (y - self.y_start);
+        (self.x_start + offset, self.x_end + offset)
+    }
+}
+
+// Counts the cells of one row that are covered by at least two of the column ranges.
+fn overlapping_columns(ranges: &mut [(i64, i64)]) -> u64 {
+    ranges.sort_unstable();
+    let mut overlapping = 0;
+    // The covered region to the right of the ranges seen so far, and the part of it covered twice.
+    let mut covered_end = i64::MIN;
+    let mut overlap_end = i64::MIN;
+    for &(start, end) in ranges.iter() {
+        let overlap_start = start.max(overlap_end + 1);
+        let overlap_stop = end.min(covered_end);
+        if overlap_start <= overlap_stop {
+            overlapping += (overlap_stop - overlap_start + 1) as u64;
+            overlap_end = overlap_stop;
+        }
+        covered_end = covered_end.max(end);
+    }
+    overlapping
+}
+
+// Counts the points covered by at least two lines without visiting every point: the lines are
+// swept row by row, and runs of rows without diagonals are counted once and multiplied.  Memory
+// use only depends on the number of lines, not on their length or the coordinate range.
+fn count_overlaps_sweep<'a>(lines: impl IntoIterator<Item = &'a Line>) -> u64 {
+    let mut spans: Vec<RowSpan> = lines.into_iter().map(RowSpan::new).collect();
+    spans.sort_by_key(|span| span.y_start);
+
+    let mut overlapping = 0;
+    let mut active: Vec<RowSpan> = Vec::new();
+    let mut ranges = Vec::new();
+    let mut next = 0;
+    let mut y = i64::MIN;
+    while next < spans.len() || !active.is_empty() {
+        if active.is_empty() {
+            y = spans[next].y_start;
+        }
+        while next < spans.len() && spans[next].y_start == y {
+            active.push(spans[next]);
+            next += 1;
+        }
+
+        ranges.clear();
+        ranges.extend(active.iter().map(|span| span.columns(y)));
+        let row_overlaps = overlapping_columns(&mut ranges);
+
+        let rows = if active.iter().all(|span| span.slope == 0) {
+            let next_start = spans.get(next).map_or(i64::MAX, |span| span.y_start);
+            let first_end = active.iter().map(|span| span.y_end).min().unwrap();
+            next_start.min(first_end + 1) - y
+        } else {
+            1
+        };
+        overlapping += row_overlaps * rows as u64;
+        y += rows;
+        active.retain(|span| span.y_end >= y);
+    }
+    overlapping
+}
+
+// Counts the points covered by at least two lines, with `mode` deciding which points a line
+// covers.
+fn count_overlaps(lines: &[Line], mode: Rasterization) -> Result<u64, LineError> {
+    if mode == Rasterization::Strict {
+        if let Some(line) = lines.iter().find(|line| !line.is_strict()) {
+            return Err(LineError::UnsupportedSlope {
+                a: line.a,
+                b: line.b,
+            });
+        }
+        return Ok(count_overlaps_sweep(lines));
+    }
+
+    let mut counter = HashMap::new();
     for line in lines {
-        for point in line.points() {
-            let count = counter.entry(point).or_insert(0u32);
-            *count += 1
+        for point in line.rasterize(mode)? {
+            *counter.entry(point).or_insert(0u32) += 1;
+        }
+    }
+    Ok(counter.into_values().filter(|v| *v > 1).count() as u64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Direction {
+    Horizontal,
+    Vertical,
+    Diagonal,
+    AntiDiagonal,
+}
+
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Horizontal,
+        Direction::Vertical,
+        Direction::Diagonal,
+        Direction::AntiDiagonal,
+    ];
+
+    // The infinite line in this direction through x,y is `a * x + b * y = offset`.
+    fn coefficients(self) -> (i64, i64) {
+        match self {
+            Direction::Horizontal => (0, 1),
+            Direction::Vertical => (1, 0),
+            Direction::Diagonal => (1, -1),
+            Direction::AntiDiagonal => (1, 1),
+        }
+    }
+
+    fn offset(self, x: i64, y: i64) -> i64 {
+        let (a, b) = self.coefficients();
+        a * x + b * y
+    }
+
+    // Where x,y lies along a line in this direction.  Lattice points on the line get consecutive
+    // positions.
+    fn position(self, x: i64, y: i64) -> i64 {
+        match self {
+            Direction::Vertical => y,
+            _ => x,
         }
     }
+}
 
-    counter.into_values().filter(|v| *v > 1).count()
+// A strict line as the range of positions it covers on the infinite line it lies on.
+#[derive(Clone, Copy)]
+struct Segment {
+    direction: Direction,
+    offset: i64,
+    start: i64,
+    end: i64,
+    min_x: i64,
+    max_x: i64,
+    min_y: i64,
+    max_y: i64,
+}
+
+impl Segment {
+    fn new(line: &Line) -> Self {
+        let (ax, ay, bx, by) = (
+            line.a.x as i64,
+            line.a.y as i64,
+            line.b.x as i64,
+            line.b.y as i64,
+        );
+        let direction = if ay == by {
+            Direction::Horizontal
+        } else if ax == bx {
+            Direction::Vertical
+        } else if (bx - ax).signum() == (by - ay).signum() {
+            Direction::Diagonal
+        } else {
+            Direction::AntiDiagonal
+        };
+        let (pa, pb) = (direction.position(ax, ay), direction.position(bx, by));
+        Self {
+            direction,
+            offset: direction.offset(ax, ay),
+            start: pa.min(pb),
+            end: pa.max(pb),
+            min_x: ax.min(bx),
+            max_x: ax.max(bx),
+            min_y: ay.min(by),
+            max_y: ay.max(by),
+        }
+    }
+
+    fn carrier(&self) -> (Direction, i64) {
+        (self.direction, self.offset)
+    }
+
+    // Assumes x,y is on the infinite line through the segment.
+    fn contains(&self, x: i64, y: i64) -> bool {
+        let position = self.direction.position(x, y);
+        self.start <= position && position <= self.end
+    }
+
+    // The lattice point two segments on different lines have in common, if any.
+    fn crossing(&self, other: &Segment) -> Option<(i64, i64)> {
+        let (a1, b1) = self.direction.coefficients();
+        let (a2, b2) = other.direction.coefficients();
+        let det = a1 * b2 - a2 * b1;
+        if det == 0 {
+            return None;
+        }
+        let x = self.offset * b2 - other.offset * b1;
+        let y = a1 * other.offset - a2 * self.offset;
+        if x % det != 0 || y % det != 0 {
+            return None;
+        }
+        let (x, y) = (x / det, y / det);
+        if self.contains(x, y) && other.contains(x, y) {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+}
+
+fn merge_ranges(ranges: &mut Vec<(i64, i64)>) {
+    ranges.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    *ranges = merged;
+}
+
+// Assumes `ranges` is merged.
+fn ranges_contain(ranges: &[(i64, i64)], position: i64) -> bool {
+    let idx = ranges.partition_point(|&(start, _)| start <= position);
+    idx > 0 && ranges[idx - 1].1 >= position
+}
+
+// Counts the points covered by at least two lines from how pairs of lines intersect, so the cost
+// only depends on the number of lines and not on their lengths.  Every such point either lies
+// where two lines on the same infinite line overlap, or is where two lines on different ones
+// cross; the first kind is counted as merged ranges per infinite line, and crossing points are
+// then added unless they're already in one of those ranges.
+fn count_overlaps_analytical(lines: &[Line]) -> Result<u64, LineError> {
+    if let Some(line) = lines.iter().find(|line| !line.is_strict()) {
+        return Err(LineError::UnsupportedSlope {
+            a: line.a,
+            b: line.b,
+        });
+    }
+    let mut segments: Vec<Segment> = lines.iter().map(Segment::new).collect();
+    segments.sort_by_key(|segment| segment.min_x);
+
+    let mut overlaps: HashMap<(Direction, i64), Vec<(i64, i64)>> = HashMap::new();
+    let mut crossings = HashSet::new();
+    // Only segments whose x ranges overlap can intersect, so sweep over x keeping those around.
+    let mut active: Vec<Segment> = Vec::new();
+    for segment in segments {
+        active.retain(|other| other.max_x >= segment.min_x);
+        for other in &active {
+            if other.max_y < segment.min_y || other.min_y > segment.max_y {
+                continue;
+            }
+            if other.carrier() == segment.carrier() {
+                let start = other.start.max(segment.start);
+                let end = other.end.min(segment.end);
+                if start <= end {
+                    overlaps
+                        .entry(segment.carrier())
+                        .or_default()
+                        .push((start, end));
+                }
+            } else if let Some(point) = segment.crossing(other) {
+                crossings.insert(point);
+            }
+        }
+        active.push(segment);
+    }
+
+    let mut overlapping = 0i64;
+    for ranges in overlaps.values_mut() {
+        merge_ranges(ranges);
+        overlapping += ranges
+            .iter()
+            .map(|(start, end)| end - start + 1)
+            .sum::<i64>();
+    }
+    // Each crossing point has been counted once for every infinite line whose overlaps contain
+    // it.  A point in the overlaps of several infinite lines is always a crossing point too, so
+    // this also stops those from being counted more than once.
+    for (x, y) in crossings {
+        let carriers = Direction::ALL
+            .iter()
+            .filter(|direction| {
+                overlaps
+                    .get(&(**direction, direction.offset(x, y)))
+                    .is_some_and(|ranges| ranges_contain(ranges, direction.position(x, y)))
+            })
+            .count() as i64;
+        overlapping += 1 - carriers;
+    }
+    Ok(overlapping as u64)
+}
+
+// The vent lines rasterized once, for answering questions about individual points.
+struct VentField {
+    lines: Vec<Line>,
+    mode: Rasterization,
+    counts: HashMap<Point, u32>,
+}
+
+impl VentField {
+    fn new(lines: Vec<Line>, mode: Rasterization) -> Result<Self, LineError> {
+        let mut counts = HashMap::new();
+        for line in &lines {
+            for point in line.rasterize(mode)? {
+                *counts.entry(point).or_insert(0) += 1;
+            }
+        }
+        Ok(Self {
+            lines,
+            mode,
+            counts,
+        })
+    }
+
+    fn count_at(&self, point: &Point) -> u32 {
+        self.counts.get(point).copied().unwrap_or(0)
+    }
+
+    fn lines_through(&self, point: &Point) -> Vec<&Line> {
+        self.lines
+            .iter()
+            .filter(|line| line.covers(point, self.mode))
+            .collect()
+    }
+
+    // The point with the most lines through it, the top-most then left-most one on ties.
+    fn most_dangerous(&self) -> Option<(Point, u32)> {
+        self.counts
+            .iter()
+            .max_by_key(|(point, &count)| (count, -point.y, -point.x))
+            .map(|(point, &count)| (*point, count))
+    }
+
+    fn count_at_least(&self, threshold: u32) -> usize {
+        self.counts.values().filter(|&&v| v >= threshold).count()
+    }
+
+    // Whether one can walk from `from` to `to` in horizontal and vertical steps without entering a
+    // point with more than `max_danger` lines through it.  Everything outside the area spanned by
+    // the lines is safe, so the search is limited to that area plus a one point margin, and
+    // endpoints outside it are moved onto the margin first.
+    fn has_safe_path(&self, from: Point, to: Point, max_danger: u32) -> bool {
+        if self.count_at(&from) > max_danger || self.count_at(&to) > max_danger {
+            return false;
+        }
+        if self.counts.is_empty() {
+            return true;
+        }
+        let xs = self.counts.keys().map(|p| p.x);
+        let (min_x, max_x) = (xs.clone().min().unwrap() - 1, xs.max().unwrap() + 1);
+        let ys = self.counts.keys().map(|p| p.y);
+        let (min_y, max_y) = (ys.clone().min().unwrap() - 1, ys.max().unwrap() + 1);
+        let clamp = |p: Point| Point::new(p.x.clamp(min_x, max_x), p.y.clamp(min_y, max_y));
+        let (from, to) = (clamp(from), clamp(to));
+
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(from);
+        queue.push_back(from);
+        while let Some(point) = queue.pop_front() {
+            if point == to {
+                return true;
+            }
+            for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let next = Point::new(point.x + dx, point.y + dy);
+                if next.x < min_x || next.x > max_x || next.y < min_y || next.y > max_y {
+                    continue;
+                }
+                if self.count_at(&next) <= max_danger && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        false
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mode = |idx: usize| {
+        args.get(idx)
+            .and_then(|arg| Rasterization::from_arg(arg))
+            .expect("Expected strict|lattice|bresenham")
+    };
+    match args.get(1).map(String::as_str) {
+        Some("--rasterize") => match count_overlaps(&read_input(), mode(2)) {
+            Ok(count) => println!("{}", count),
+            Err(err) => println!("{}", err),
+        },
+        Some("--analytical") => match count_overlaps_analytical(&read_input()) {
+            Ok(count) => println!("{}", count),
+            Err(err) => println!("{}", err),
+        },
+        // --field <mode> [<from> <to> <max danger>]
+        Some("--field") => {
+            let field = VentField::new(read_input(), mode(2)).unwrap_or_else(|e| panic!("{}", e));
+            println!("{} points with overlaps", field.count_at_least(2));
+            if let Some((point, count)) = field.most_dangerous() {
+                println!(
+                    "most dangerous: {},{} with {} lines",
+                    point.x, point.y, count
+                );
+                for line in field.lines_through(&point) {
+                    println!("  {},{} -> {},{}", line.a.x, line.a.y, line.b.x, line.b.y);
+                }
+            }
+            if let (Some(from), Some(to), Some(max_danger)) =
+                (args.get(3), args.get(4), args.get(5))
+            {
+                let max_danger = max_danger.parse().expect("Invalid danger level");
+                let safe =
+                    field.has_safe_path(from.as_str().into(), to.as_str().into(), max_danger);
+                println!("safe path from {} to {}: {}", from, to, safe);
+            }
+        }
+        // --3d <file> [sparse|dense]
+        Some("--3d") => {
+            let input = fs::read_to_string(args.get(2).expect("Expected --3d <file>"))
+                .expect("File missing");
+            let lines: Vec<Line3> = input.lines().map(|l| l.into()).collect();
+            let counting = match args.get(3).map(String::as_str) {
+                Some("dense") => Counting::Dense,
+                _ => Counting::Sparse,
+            };
+            match count_overlaps_3d(&lines, counting) {
+                Ok(count) => println!("{}", count),
+                Err(err) => println!("{}", err),
+            }
+        }
+        _ => {
+            println!("{}", part1());
+            println!("{}", part2());
+        }
+    }
+}
+
+fn read_input() -> Vec<Line> {
+    let input = fs::read_to_string("data/day_05.txt").expect("File missing");
+    input.lines().map(|l| l.into()).collect()
+}
+
+fn part1() -> u64 {
+    let lines = read_input();
+    count_overlaps_sweep(lines.iter().filter(|line| !line.is_diagonal()))
+}
+
+fn part2() -> u64 {
+    let lines = read_input();
+    count_overlaps_sweep(&lines)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     #[test]
     fn test_part1() {
@@ -120,10 +830,342 @@ mod tests {
         assert_eq!(part2(), 18423);
     }
 
+    fn line_points(line: &Line) -> Vec<Point> {
+        line.rasterize(Rasterization::Strict)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // The original approach, counting every point in a hash map.
+    fn count_overlaps_enumerated<'a>(lines: impl IntoIterator<Item = &'a Line>) -> u64 {
+        let mut counter = HashMap::new();
+        for line in lines {
+            for point in line_points(line) {
+                *counter.entry(point).or_insert(0u32) += 1;
+            }
+        }
+        counter.into_values().filter(|v| *v > 1).count() as u64
+    }
+
+    #[test]
+    fn test_sweep_matches_enumeration() {
+        let lines = read_input();
+        assert_eq!(
+            count_overlaps_enumerated(lines.iter().filter(|line| !line.is_diagonal())),
+            part1()
+        );
+        assert_eq!(count_overlaps_enumerated(&lines), part2());
+
+        let mut rng = StdRng::seed_from_u64(39);
+        for _ in 0..100 {
+            let lines: Vec<Line> = (0..20)
+                .map(|_| {
+                    let a = Point::new(rng.gen_range(0, 30), rng.gen_range(0, 30));
+                    let length = rng.gen_range(0, 15);
+                    let (dx, dy) =
+                        [(1, 0), (0, 1), (1, 1), (1, -1), (-1, 0), (-1, 1)][rng.gen_range(0, 6)];
+                    let b = Point::new(a.x + dx * length, a.y + dy * length);
+                    Line::new(a, b)
+                })
+                .collect();
+            assert_eq!(
+                count_overlaps_sweep(&lines),
+                count_overlaps_enumerated(&lines)
+            );
+        }
+    }
+
+    #[test]
+    fn test_sweep_long_lines() {
+        let lines = vec![
+            Line::from("5,0 -> 5,1000000000"),
+            Line::from("5,2000000000 -> 5,500000000"),
+            Line::from("0,7 -> 1000000000,7"),
+            Line::from("3,3 -> 9,9"),
+        ];
+        // The vertical lines share 500_000_001 points, the first one crosses the horizontal line
+        // at 5,7 and the diagonal at 5,5, and the diagonal crosses the horizontal line at 7,7.
+        assert_eq!(count_overlaps_sweep(&lines), 500_000_001 + 3);
+    }
+
+    #[test]
+    fn test_lattice_long_lines() {
+        let horizontal = Line::from("0,0 -> 50000,0");
+        let points = horizontal.rasterize(Rasterization::Lattice).unwrap();
+        assert_eq!(points.len(), 50_001);
+        assert_eq!(points[49_999], Point::new(49_999, 0));
+        assert_eq!(points.last(), Some(&Point::new(50_000, 0)));
+
+        let sloped = Line::from("100000,0 -> 0,50000");
+        let points = sloped.rasterize(Rasterization::Lattice).unwrap();
+        assert_eq!(points.len(), 50_001);
+        assert_eq!(points[1], Point::new(99_998, 1));
+        assert_eq!(points.last(), Some(&Point::new(0, 50_000)));
+
+        let lines = vec![horizontal, Line::from("25000,0 -> 25000,5")];
+        assert_eq!(count_overlaps(&lines, Rasterization::Lattice), Ok(1));
+    }
+
+    #[test]
+    fn test_rasterization_modes() {
+        let line = Line::from("0,0 -> 6,4");
+        assert_eq!(
+            line.rasterize(Rasterization::Strict),
+            Err(LineError::UnsupportedSlope {
+                a: Point::new(0, 0),
+                b: Point::new(6, 4)
+            })
+        );
+        assert_eq!(
+            line.rasterize(Rasterization::Lattice),
+            Ok(vec![Point::new(0, 0), Point::new(3, 2), Point::new(6, 4)])
+        );
+        assert_eq!(
+            Line::from("4,2 -> 0,0").rasterize(Rasterization::Bresenham),
+            Ok(vec![
+                Point::new(4, 2),
+                Point::new(3, 1),
+                Point::new(2, 1),
+                Point::new(1, 0),
+                Point::new(0, 0)
+            ])
+        );
+        assert_eq!(
+            Line::from("3,3 -> 3,3").rasterize(Rasterization::Lattice),
+            Ok(vec![Point::new(3, 3)])
+        );
+
+        for line in read_input() {
+            let strict = line.rasterize(Rasterization::Strict).unwrap();
+            assert_eq!(line.rasterize(Rasterization::Lattice).unwrap(), strict);
+            assert_eq!(line.rasterize(Rasterization::Bresenham).unwrap(), strict);
+        }
+    }
+
+    #[test]
+    fn test_bresenham_is_connected() {
+        let mut rng = StdRng::seed_from_u64(40);
+        for _ in 0..200 {
+            let a = Point::new(rng.gen_range(-50, 50), rng.gen_range(-50, 50));
+            let b = Point::new(rng.gen_range(-50, 50), rng.gen_range(-50, 50));
+            let line = Line::new(a, b);
+            let points = line.rasterize(Rasterization::Bresenham).unwrap();
+            assert_eq!(points.first(), Some(&a));
+            assert_eq!(points.last(), Some(&b));
+            assert_eq!(
+                points.len() as i32,
+                (a.x - b.x).abs().max((a.y - b.y).abs()) + 1
+            );
+            for pair in points.windows(2) {
+                assert!((pair[0].x - pair[1].x).abs() <= 1);
+                assert!((pair[0].y - pair[1].y).abs() <= 1);
+            }
+            for point in line.rasterize(Rasterization::Lattice).unwrap() {
+                assert!(points.contains(&point));
+            }
+        }
+    }
+
+    #[test]
+    fn test_count_overlaps_modes() {
+        let lines = vec![Line::from("0,0 -> 6,4"), Line::from("0,4 -> 6,0")];
+        assert!(count_overlaps(&lines, Rasterization::Strict).is_err());
+        // Both lines go through 3,2.
+        assert_eq!(count_overlaps(&lines, Rasterization::Lattice), Ok(1));
+        assert_eq!(count_overlaps(&lines, Rasterization::Bresenham), Ok(1));
+
+        let lines = read_input();
+        assert_eq!(count_overlaps(&lines, Rasterization::Strict), Ok(part2()));
+        assert_eq!(count_overlaps(&lines, Rasterization::Lattice), Ok(part2()));
+    }
+
+    fn example() -> Vec<Line> {
+        "0,9 -> 5,9\n8,0 -> 0,8\n9,4 -> 3,4\n2,2 -> 2,1\n7,0 -> 7,4\n\
+         6,4 -> 2,0\n0,9 -> 2,9\n3,4 -> 1,4\n0,0 -> 8,8\n5,5 -> 8,2"
+            .lines()
+            .map(|l| l.into())
+            .collect()
+    }
+
+    #[test]
+    fn test_vent_field_queries() {
+        let field = VentField::new(example(), Rasterization::Strict).unwrap();
+        assert_eq!(field.count_at(&Point::new(0, 9)), 2);
+        assert_eq!(field.count_at(&Point::new(9, 9)), 0);
+        assert_eq!(field.count_at_least(2), 12);
+        assert_eq!(field.count_at_least(3), 2);
+        assert_eq!(field.most_dangerous(), Some((Point::new(4, 4), 3)));
+
+        let through: Vec<String> = field
+            .lines_through(&Point::new(4, 4))
+            .iter()
+            .map(|line| format!("{},{} -> {},{}", line.a.x, line.a.y, line.b.x, line.b.y))
+            .collect();
+        assert_eq!(through, vec!["8,0 -> 0,8", "9,4 -> 3,4", "0,0 -> 8,8"]);
+        assert!(field.lines_through(&Point::new(9, 9)).is_empty());
+
+        let field = VentField::new(read_input(), Rasterization::Strict).unwrap();
+        assert_eq!(field.count_at_least(2) as u64, part2());
+    }
+
+    #[test]
+    fn test_vent_field_far_endpoints() {
+        let field = VentField::new(example(), Rasterization::Strict).unwrap();
+        let far = Point::new(100_000, 100_000);
+        assert!(field.has_safe_path(Point::new(-100_000, 0), far, 0));
+        assert!(field.has_safe_path(far, Point::new(100_000, -100_000), 0));
+        // (2,2) is where two lines cross, and no path avoids it with a danger limit below 2.
+        assert!(!field.has_safe_path(Point::new(2, 2), far, 1));
+        assert!(field.has_safe_path(Point::new(2, 2), far, 2));
+
+        let walled = VentField::new(read_input(), Rasterization::Strict).unwrap();
+        assert!(walled.has_safe_path(Point::new(0, 0), far, 1));
+    }
+
+    #[test]
+    fn test_vent_field_safe_path() {
+        // A square drawn twice, so its border has two lines through every point and four through
+        // the corners.
+        let border = "2,2 -> 6,2\n6,2 -> 6,6\n6,6 -> 2,6\n2,6 -> 2,2";
+        let lines = border
+            .lines()
+            .chain(border.lines())
+            .map(|l| l.into())
+            .collect();
+        let field = VentField::new(lines, Rasterization::Strict).unwrap();
+        let inside = Point::new(4, 4);
+        let outside = Point::new(10, 0);
+        assert!(!field.has_safe_path(inside, outside, 1));
+        assert!(field.has_safe_path(inside, Point::new(5, 3), 0));
+        assert!(field.has_safe_path(Point::new(0, 0), outside, 0));
+        assert!(field.has_safe_path(inside, outside, 2));
+        assert!(!field.has_safe_path(Point::new(2, 2), outside, 3));
+        assert!(field.has_safe_path(Point::new(2, 2), outside, 4));
+    }
+
+    #[test]
+    fn test_line3_points() {
+        let line = Line3::from("1,1,1 -> 3,3,3");
+        assert_eq!(
+            line.points(),
+            Ok(vec![
+                Point3::new(1, 1, 1),
+                Point3::new(2, 2, 2),
+                Point3::new(3, 3, 3)
+            ])
+        );
+        let line = Line3::from("5,0,2 -> 3,2,2");
+        assert_eq!(
+            line.points(),
+            Ok(vec![
+                Point3::new(5, 0, 2),
+                Point3::new(4, 1, 2),
+                Point3::new(3, 2, 2)
+            ])
+        );
+        assert_eq!(Line3::from("0,0,7 -> 0,0,4").points().unwrap().len(), 4);
+        assert_eq!(
+            Line3::from("0,0,0 -> 2,1,0").points(),
+            Err(LineError::UnsupportedSlope3 {
+                a: Point3::new(0, 0, 0),
+                b: Point3::new(2, 1, 0)
+            })
+        );
+    }
+
+    #[test]
+    fn test_count_overlaps_3d() {
+        let lines: Vec<Line3> = [
+            // Three lines through the middle of a cube, crossing at 2,2,2.
+            "0,0,0 -> 4,4,4",
+            "2,0,2 -> 2,4,2",
+            "0,4,0 -> 4,0,4",
+            // Overlaps the second line on 2,3,2 and 2,4,2.
+            "2,3,2 -> 2,6,2",
+            // Parallel to the first line one step up, never touching it.
+            "0,0,1 -> 3,3,4",
+            // Crosses the first line at 4,4,4 only.
+            "4,4,4 -> 4,4,9",
+        ]
+        .iter()
+        .map(|&s| s.into())
+        .collect();
+        for &counting in &[Counting::Sparse, Counting::Dense] {
+            assert_eq!(count_overlaps_3d(&lines, counting), Ok(4));
+            assert_eq!(count_overlaps_3d(&[], counting), Ok(0));
+        }
+
+        // Lines in a single z plane agree with the 2D count.
+        let flat: Vec<Line3> = read_input()
+            .iter()
+            .map(|line| {
+                Line3::new(
+                    Point3::new(line.a.x, line.a.y, 3),
+                    Point3::new(line.b.x, line.b.y, 3),
+                )
+            })
+            .collect();
+        assert_eq!(count_overlaps_3d(&flat, Counting::Sparse), Ok(part2()));
+        assert_eq!(count_overlaps_3d(&flat, Counting::Dense), Ok(part2()));
+    }
+
+    #[test]
+    fn test_analytical_matches_enumeration() {
+        let lines = read_input();
+        let straight: Vec<Line> = lines
+            .iter()
+            .filter(|line| !line.is_diagonal())
+            .map(|line| Line::new(line.a, line.b))
+            .collect();
+        assert_eq!(count_overlaps_analytical(&straight), Ok(part1()));
+        assert_eq!(count_overlaps_analytical(&lines), Ok(part2()));
+        assert_eq!(count_overlaps_analytical(&example()), Ok(12));
+
+        let mut rng = StdRng::seed_from_u64(43);
+        for _ in 0..300 {
+            let lines: Vec<Line> = (0..15)
+                .map(|_| {
+                    let a = Point::new(rng.gen_range(0, 12), rng.gen_range(0, 12));
+                    let length = rng.gen_range(0, 8);
+                    let (dx, dy) =
+                        [(1, 0), (0, 1), (1, 1), (1, -1), (-1, 0), (-1, -1)][rng.gen_range(0, 6)];
+                    Line::new(a, Point::new(a.x + dx * length, a.y + dy * length))
+                })
+                .collect();
+            assert_eq!(
+                count_overlaps_analytical(&lines),
+                Ok(count_overlaps_enumerated(&lines))
+            );
+        }
+
+        assert!(count_overlaps_analytical(&[Line::from("0,0 -> 2,1")]).is_err());
+    }
+
+    #[test]
+    fn test_analytical_huge_coordinates() {
+        let lines: Vec<Line> = [
+            "0,0 -> 4000000,4000000",
+            "1000000,1000000 -> 3000000,3000000",
+            "0,4000000 -> 4000000,0",
+            "0,2000000 -> 4000000,2000000",
+            "2000001,0 -> 2000001,4000000",
+        ]
+        .iter()
+        .map(|&s| s.into())
+        .collect();
+        // The diagonals overlap on 2_000_001 points, which include where everything but the
+        // vertical line crosses.  The vertical line crosses the overlap as well, and adds two
+        // more points where it crosses the anti-diagonal and the horizontal line.
+        assert_eq!(count_overlaps_analytical(&lines), Ok(2_000_003));
+
+        // These cross halfway between lattice points.
+        let lines = vec![Line::from("0,1 -> 10,11"), Line::from("0,10 -> 10,0")];
+        assert_eq!(count_overlaps_analytical(&lines), Ok(0));
+    }
+
     #[test]
     fn test_line_points() {
         let line = Line::from("10,20 -> 13,20");
-        let points = line.points();
+        let points = line_points(&line);
         assert_eq!(points.len(), 4);
         assert_eq!(points[0], Point::new(10, 20));
         assert_eq!(points[1], Point::new(11, 20));
@@ -131,7 +1173,7 @@ mod tests {
         assert_eq!(points[3], Point::new(13, 20));
 
         let line = Line::from("10,20 -> 7,17");
-        let points = line.points();
+        let points = line_points(&line);
         assert_eq!(points.len(), 4);
         assert_eq!(points[0], Point::new(10, 20));
         assert_eq!(points[1], Point::new(9, 19));
@@ -1,29 +1,119 @@
-use cached::proc_macro::cached;
-use std::fs;
+use num_bigint::BigUint;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::{env, fs};
+
+// How a species of fish reproduces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PopulationModel {
+    // Timer a fish is reset to after spawning.
+    reset_timer: u32,
+    // Timer a newborn fish starts with.
+    newborn_timer: u32,
+    // Fish die once they're this many days old.
+    max_lifespan: Option<u32>,
+    offspring_per_spawn: u32,
+}
+
+impl PopulationModel {
+    const LANTERNFISH: PopulationModel = PopulationModel {
+        reset_timer: 6,
+        newborn_timer: 8,
+        max_lifespan: None,
+        offspring_per_spawn: 1,
+    };
+}
 
 struct Fish {
-    days_until_spawn: u8,
+    days_until_spawn: u32,
+    age: u32,
 }
 
 impl Fish {
-    fn new(days_until_spawn: u8) -> Self {
-        Self { days_until_spawn }
+    fn new(days_until_spawn: u32) -> Self {
+        Self {
+            days_until_spawn,
+            age: 0,
+        }
     }
 
-    fn advance_day(&mut self) -> Option<Fish> {
+    // Returns the newborns.  A fish that died of old age doesn't spawn anymore.
+    fn advance_day(&mut self, model: &PopulationModel) -> Vec<Fish> {
+        if !self.is_alive(model) {
+            return Vec::new();
+        }
+        self.age += 1;
         if self.days_until_spawn == 0 {
-            self.days_until_spawn = 6;
-            Some(Fish::new(8))
+            self.days_until_spawn = model.reset_timer;
+            (0..model.offspring_per_spawn)
+                .map(|_| Fish::new(model.newborn_timer))
+                .collect()
         } else {
             self.days_until_spawn -= 1;
-            None
+            Vec::new()
         }
     }
+
+    fn is_alive(&self, model: &PopulationModel) -> bool {
+        model
+            .max_lifespan
+            .is_none_or(|lifespan| self.age < lifespan)
+    }
 }
 
 fn main() {
     println!("{}", part1());
     println!("{}", part2());
+    let args: Vec<String> = env::args().collect();
+    let model = PopulationModel::LANTERNFISH;
+    if let Some(days) = flag_value(&args, "--days", 1) {
+        let population = exact_population_after_days(&read_input(), &model, days);
+        println!("{}", summarize(&population, 20));
+    }
+    if let Some(days) = flag_value(&args, "--histogram", 1) {
+        let series = histogram_series(&read_input(), &model, days);
+        write_csv(&series, io::stdout()).expect("I/O error");
+    }
+    if let Some(days) = flag_value(&args, "--series", 1) {
+        let mut table = FamilyTable::new(model, days);
+        for (day, population) in table.time_series(&read_input(), days).iter().enumerate() {
+            println!("{},{}", day, population);
+        }
+    }
+    if let Some(days) = flag_value(&args, "--growth", 1) {
+        let stats = GrowthStats::new(&histogram_series(&read_input(), &model, days), &model);
+        if let Some(Some(ratio)) = stats.ratios.last() {
+            println!("growth ratio on day {}: {:.9}", days, ratio);
+        }
+        if let Some(eigenvalue) = stats.eigenvalue {
+            println!("dominant eigenvalue: {:.9}", eigenvalue);
+        }
+        match stats.converged_after(1e-9) {
+            Some(day) => println!("converged from day {}", day),
+            None => println!("not converged"),
+        }
+    }
+    // --modulo <days> <modulus>
+    if let (Some(days), Some(modulus)) = (
+        flag_value(&args, "--modulo", 1),
+        flag_value(&args, "--modulo", 2),
+    ) {
+        match population_after_days_mod(&read_input(), &model, days, modulus) {
+            Some(population) => println!("{}", population),
+            None => println!("no modular count for a mortal model or a zero modulus"),
+        }
+    }
+}
+
+// The number `offset` places after `flag`, if the flag was passed.
+fn flag_value<T: std::str::FromStr>(args: &[String], flag: &str, offset: usize) -> Option<T> {
+    let idx = args.iter().position(|arg| arg == flag)?;
+    let value = args.get(idx + offset).expect("Missing argument");
+    Some(
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("Invalid argument {:?}", value)),
+    )
 }
 
 fn read_input() -> Vec<Fish> {
@@ -35,43 +125,448 @@ fn read_input() -> Vec<Fish> {
 }
 
 fn part1() -> usize {
-    let mut fishes = read_input();
-    for _ in 0..80 {
+    let fishes = read_input();
+    simulate(fishes, &PopulationModel::LANTERNFISH, 80).len()
+}
+
+fn simulate(mut fishes: Vec<Fish>, model: &PopulationModel, days: u32) -> Vec<Fish> {
+    fishes.retain(|fish| fish.is_alive(model));
+    for _ in 0..days {
         let mut new_fishes = Vec::new();
         for fish in fishes.iter_mut() {
-            if let Some(new_fish) = fish.advance_day() {
-                new_fishes.push(new_fish);
-            }
+            new_fishes.append(&mut fish.advance_day(model));
         }
         fishes.append(&mut new_fishes);
+        fishes.retain(|fish| fish.is_alive(model));
     }
-    fishes.len()
+    fishes
 }
 
-fn part2() -> u64 {
+fn part2() -> BigUint {
     let fishes = read_input();
-    let mut num_fish_after_256_days = 0u64;
-    for fish in fishes {
-        num_fish_after_256_days += family_size_after_days(fish.days_until_spawn as u64, 256);
+    exact_population_after_days(&fishes, &PopulationModel::LANTERNFISH, 256)
+}
+
+// Number of distinct timer values, from 0 up to the largest the model or any input fish uses.
+fn timer_count(fishes: &[Fish], model: &PopulationModel) -> usize {
+    fishes
+        .iter()
+        .map(|fish| fish.days_until_spawn)
+        .chain(vec![model.reset_timer, model.newborn_timer])
+        .max()
+        .unwrap() as usize
+        + 1
+}
+
+// Counts fish by age and timer, so there's no overflow however many days pass.  Immortal fish all
+// share age 0, and a lifespan of 0 means every fish is dead from the start.
+#[derive(Debug, Clone)]
+struct Census {
+    model: PopulationModel,
+    timers: usize,
+    counts: Vec<Vec<BigUint>>,
+}
+
+impl Census {
+    fn new(fishes: &[Fish], model: &PopulationModel) -> Self {
+        let ages = model.max_lifespan.unwrap_or(1) as usize;
+        let timers = timer_count(fishes, model);
+        let mut counts = vec![vec![BigUint::default(); timers]; ages];
+        if ages > 0 {
+            for fish in fishes {
+                counts[0][fish.days_until_spawn as usize] += 1u32;
+            }
+        }
+        Self {
+            model: *model,
+            timers,
+            counts,
+        }
+    }
+
+    fn timers(&self) -> usize {
+        self.timers
     }
-    num_fish_after_256_days
+
+    fn advance_day(&mut self) {
+        let model = self.model;
+        let ages = self.counts.len();
+        let mut next = vec![vec![BigUint::default(); self.timers()]; ages];
+        for (age, by_timer) in self.counts.iter().enumerate() {
+            let next_age = if model.max_lifespan.is_some() {
+                age + 1
+            } else {
+                0
+            };
+            for (timer, count) in by_timer.iter().enumerate() {
+                if count == &BigUint::default() {
+                    continue;
+                }
+                let next_timer = if timer == 0 {
+                    next[0][model.newborn_timer as usize] += count * model.offspring_per_spawn;
+                    model.reset_timer as usize
+                } else {
+                    timer - 1
+                };
+                if next_age < ages {
+                    next[next_age][next_timer] += count;
+                }
+            }
+        }
+        self.counts = next;
+    }
+
+    // Fish per timer value, whatever their age.
+    fn histogram(&self) -> Vec<BigUint> {
+        (0..self.timers())
+            .map(|timer| self.counts.iter().map(|by_timer| &by_timer[timer]).sum())
+            .collect()
+    }
+
+    fn total(&self) -> BigUint {
+        self.counts.iter().flatten().sum()
+    }
+}
+
+fn exact_population_after_days(fishes: &[Fish], model: &PopulationModel, days: u64) -> BigUint {
+    let mut census = Census::new(fishes, model);
+    for _ in 0..days {
+        census.advance_day();
+    }
+    census.total()
 }
 
-#[cached]
-fn family_size_after_days(spawn_timer: u64, days: u64) -> u64 {
-    if days == 0 {
-        1
-    } else if spawn_timer == 0 {
-        family_size_after_days(6, days - 1) + family_size_after_days(8, days - 1)
+// The per-timer histogram on each day from 0 through `days`.
+fn histogram_series(fishes: &[Fish], model: &PopulationModel, days: usize) -> Vec<Vec<BigUint>> {
+    let mut census = Census::new(fishes, model);
+    let mut series = vec![census.histogram()];
+    for _ in 0..days {
+        census.advance_day();
+        series.push(census.histogram());
+    }
+    series
+}
+
+fn write_csv<W: Write>(series: &[Vec<BigUint>], mut out: W) -> io::Result<()> {
+    let timers = series.first().map_or(0, Vec::len);
+    let header: Vec<String> = (0..timers)
+        .map(|timer| format!("timer_{}", timer))
+        .collect();
+    writeln!(out, "day,{},total", header.join(","))?;
+    for (day, histogram) in series.iter().enumerate() {
+        let counts: Vec<String> = histogram.iter().map(BigUint::to_string).collect();
+        let total: BigUint = histogram.iter().sum();
+        writeln!(out, "{},{},{}", day, counts.join(","), total)?;
+    }
+    Ok(())
+}
+
+// Ratios between the populations of consecutive days.  They converge to the dominant eigenvalue
+// as long as the spawning cycles (reset and newborn timers plus one) are coprime; otherwise they
+// keep oscillating.
+#[derive(Debug, Clone, PartialEq)]
+struct GrowthStats {
+    // `None` where the previous day had no fish.
+    ratios: Vec<Option<f64>>,
+    eigenvalue: Option<f64>,
+}
+
+impl GrowthStats {
+    fn new(series: &[Vec<BigUint>], model: &PopulationModel) -> Self {
+        let totals: Vec<BigUint> = series
+            .iter()
+            .map(|histogram| histogram.iter().sum())
+            .collect();
+        let ratios = totals
+            .windows(2)
+            .map(|pair| ratio(&pair[1], &pair[0]))
+            .collect();
+        Self {
+            ratios,
+            eigenvalue: dominant_eigenvalue(model),
+        }
+    }
+
+    // First day from which every ratio is within `tolerance` of the eigenvalue.
+    fn converged_after(&self, tolerance: f64) -> Option<usize> {
+        let eigenvalue = self.eigenvalue?;
+        let close =
+            |ratio: &Option<f64>| ratio.is_some_and(|r| (r - eigenvalue).abs() <= tolerance);
+        let unconverged = self.ratios.iter().rposition(|r| !close(r));
+        match unconverged {
+            None if self.ratios.is_empty() => None,
+            None => Some(1),
+            Some(idx) if idx + 1 < self.ratios.len() => Some(idx + 2),
+            Some(_) => None,
+        }
+    }
+}
+
+fn ratio(numerator: &BigUint, denominator: &BigUint) -> Option<f64> {
+    if denominator == &BigUint::default() {
+        return None;
+    }
+    // Drop low bits so both fit in a u64 without losing meaningful precision.
+    let shift = numerator.bits().max(denominator.bits()).saturating_sub(64);
+    let to_f64 = |n: &BigUint| u64::try_from(&(n >> shift)).unwrap() as f64;
+    Some(to_f64(numerator) / to_f64(denominator))
+}
+
+// Births on day t come from fish born one reset cycle and one newborn cycle earlier, so the growth
+// rate solves 1 = x^-(reset + 1) + offspring * x^-(newborn + 1).  Mortal models aren't covered.
+fn dominant_eigenvalue(model: &PopulationModel) -> Option<f64> {
+    if model.max_lifespan.is_some() {
+        return None;
+    }
+    let offspring = model.offspring_per_spawn as f64;
+    let births_per_fish = |x: f64| {
+        x.powi(-(model.reset_timer as i32 + 1))
+            + offspring * x.powi(-(model.newborn_timer as i32 + 1))
+    };
+    let (mut low, mut high) = (1.0, 2.0 + offspring);
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if births_per_fish(mid) > 1.0 {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some(low)
+}
+
+// Shows the leading digits and the digit count of numbers too long to print whole.
+fn summarize(number: &BigUint, leading_digits: usize) -> String {
+    let digits = number.to_string();
+    if digits.len() <= leading_digits {
+        format!("{} ({} digits)", digits, digits.len())
     } else {
-        family_size_after_days(spawn_timer - 1, days - 1)
+        format!("{}... ({} digits)", &digits[..leading_digits], digits.len())
     }
 }
 
+// `sizes[days][age][timer]` is how many fish a single fish of that age and timer turns into after
+// that many days.  Ages only matter for mortal fish, so immortal ones all share age 0.  Sizes
+// grow exponentially, so they're big integers like `Census` counts.
+#[derive(Debug, Clone)]
+struct FamilyTable {
+    model: PopulationModel,
+    sizes: Vec<Vec<Vec<BigUint>>>,
+}
+
+impl FamilyTable {
+    fn new(model: PopulationModel, max_days: usize) -> Self {
+        let ages = model.max_lifespan.unwrap_or(1) as usize;
+        let timers = model.reset_timer.max(model.newborn_timer) as usize + 1;
+        let mut table = Self {
+            model,
+            sizes: vec![vec![vec![BigUint::from(1u32); timers]; ages]],
+        };
+        table.extend(max_days);
+        table
+    }
+
+    fn max_days(&self) -> usize {
+        self.sizes.len() - 1
+    }
+
+    fn extend(&mut self, max_days: usize) {
+        let model = self.model;
+        while self.max_days() < max_days {
+            let prev = &self.sizes[self.max_days()];
+            let ages = prev.len();
+            let newborns = if ages > 0 {
+                &prev[0][model.newborn_timer as usize] * model.offspring_per_spawn
+            } else {
+                BigUint::default()
+            };
+            let next = (0..ages)
+                .map(|age| {
+                    let next_age = if model.max_lifespan.is_some() {
+                        age + 1
+                    } else {
+                        0
+                    };
+                    (0..prev[age].len())
+                        .map(|timer| {
+                            let (next_timer, born) = if timer == 0 {
+                                (model.reset_timer as usize, newborns.clone())
+                            } else {
+                                (timer - 1, BigUint::default())
+                            };
+                            if next_age < ages {
+                                born + &prev[next_age][next_timer]
+                            } else {
+                                born
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+            self.sizes.push(next);
+        }
+    }
+
+    // Timers beyond the table's just count down until they're in it.
+    fn family_size(&mut self, timer: u32, days: usize) -> BigUint {
+        let top = self.model.reset_timer.max(self.model.newborn_timer) as usize;
+        let skipped = (timer as usize).saturating_sub(top).min(days);
+        let age = if self.model.max_lifespan.is_some() {
+            skipped
+        } else {
+            0
+        };
+        self.extend(days - skipped);
+        // Still above the table means no days are left, when every timer counts the same.
+        let timer = (timer as usize - skipped).min(top);
+        self.sizes[days - skipped]
+            .get(age)
+            .map_or_else(BigUint::default, |by_timer| by_timer[timer].clone())
+    }
+
+    fn population(&mut self, fishes: &[Fish], days: usize) -> BigUint {
+        fishes
+            .iter()
+            .map(|fish| self.family_size(fish.days_until_spawn, days))
+            .sum()
+    }
+
+    // Population on each day from 0 through `days`.
+    fn time_series(&mut self, fishes: &[Fish], days: usize) -> Vec<BigUint> {
+        (0..=days).map(|day| self.population(fishes, day)).collect()
+    }
+}
+
+// Counts fish per timer value modulo `modulus`, one day being a multiplication by this matrix.
+// Mortal fish would need their age tracked too, so only immortal models are supported, and the
+// modulus has to be positive.
+#[derive(Debug, Clone, PartialEq)]
+struct TransitionMatrix {
+    size: usize,
+    modulus: u64,
+    entries: Vec<u64>,
+}
+
+impl TransitionMatrix {
+    fn identity(size: usize, modulus: u64) -> Self {
+        let mut entries = vec![0; size * size];
+        for i in 0..size {
+            entries[i * size + i] = 1 % modulus;
+        }
+        Self {
+            size,
+            modulus,
+            entries,
+        }
+    }
+
+    // `size` has to cover every timer value, see `timer_count`.
+    fn for_model(model: &PopulationModel, modulus: u64, size: usize) -> Option<Self> {
+        if model.max_lifespan.is_some() || modulus == 0 {
+            return None;
+        }
+        let mut matrix = Self {
+            size,
+            modulus,
+            entries: vec![0; size * size],
+        };
+        for timer in 1..size {
+            matrix.add(timer - 1, timer, 1);
+        }
+        matrix.add(model.reset_timer as usize, 0, 1);
+        matrix.add(
+            model.newborn_timer as usize,
+            0,
+            model.offspring_per_spawn as u64,
+        );
+        Some(matrix)
+    }
+
+    fn add(&mut self, row: usize, col: usize, amount: u64) {
+        let entry = &mut self.entries[row * self.size + col];
+        *entry = ((*entry as u128 + amount as u128) % self.modulus as u128) as u64;
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let size = self.size;
+        let mut entries = vec![0; size * size];
+        for row in 0..size {
+            for col in 0..size {
+                let sum: u128 = (0..size)
+                    .map(|k| {
+                        self.entries[row * size + k] as u128 * other.entries[k * size + col] as u128
+                            % self.modulus as u128
+                    })
+                    .sum();
+                entries[row * size + col] = (sum % self.modulus as u128) as u64;
+            }
+        }
+        Self {
+            size,
+            modulus: self.modulus,
+            entries,
+        }
+    }
+
+    fn pow(&self, mut exponent: u64) -> Self {
+        let mut result = Self::identity(self.size, self.modulus);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            base = base.mul(&base);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    fn apply(&self, buckets: &[u64]) -> Vec<u64> {
+        (0..self.size)
+            .map(|row| {
+                let terms = (0..self.size).map(|col| {
+                    (self.entries[row * self.size + col] as u128 * buckets[col] as u128
+                        % self.modulus as u128) as u64
+                });
+                sum_mod(terms, self.modulus)
+            })
+            .collect()
+    }
+}
+
+// Every value is below `modulus`, so a u128 can't overflow adding up 2^64 of them.
+fn sum_mod(values: impl IntoIterator<Item = u64>, modulus: u64) -> u64 {
+    let sum: u128 = values.into_iter().map(u128::from).sum();
+    (sum % modulus as u128) as u64
+}
+
+fn timer_buckets(fishes: &[Fish], size: usize, modulus: u64) -> Vec<u64> {
+    let mut buckets = vec![0; size];
+    for fish in fishes {
+        buckets[fish.days_until_spawn as usize] += 1;
+    }
+    buckets.iter().map(|count| count % modulus).collect()
+}
+
+fn population_after_days_mod(
+    fishes: &[Fish],
+    model: &PopulationModel,
+    days: u64,
+    modulus: u64,
+) -> Option<u64> {
+    let matrix = TransitionMatrix::for_model(model, modulus, timer_count(fishes, model))?;
+    let buckets = timer_buckets(fishes, matrix.size, modulus);
+    Some(sum_mod(matrix.pow(days).apply(&buckets), modulus))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn population_after_days(fishes: &[Fish], model: PopulationModel, days: u64) -> BigUint {
+        FamilyTable::new(model, days as usize).population(fishes, days as usize)
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(part1(), 362_740);
@@ -79,6 +574,340 @@ mod tests {
 
     #[test]
     fn test_part2() {
-        assert_eq!(part2(), 1_644_874_076_764);
+        assert_eq!(part2(), BigUint::from(1_644_874_076_764u64));
+    }
+
+    fn example() -> Vec<Fish> {
+        [3, 4, 3, 1, 2]
+            .iter()
+            .map(|&timer| Fish::new(timer))
+            .collect()
+    }
+
+    #[test]
+    fn test_models_agree() {
+        let models = [
+            PopulationModel::LANTERNFISH,
+            PopulationModel {
+                reset_timer: 2,
+                newborn_timer: 4,
+                max_lifespan: None,
+                offspring_per_spawn: 3,
+            },
+            PopulationModel {
+                reset_timer: 3,
+                newborn_timer: 5,
+                max_lifespan: Some(12),
+                offspring_per_spawn: 2,
+            },
+            PopulationModel {
+                max_lifespan: Some(1),
+                ..PopulationModel::LANTERNFISH
+            },
+        ];
+        for model in &models {
+            for days in [0, 1, 5, 18, 40] {
+                assert_eq!(
+                    BigUint::from(simulate(example(), model, days).len()),
+                    population_after_days(&example(), *model, days as u64),
+                    "{:?} after {} days",
+                    model,
+                    days
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_lanternfish_example() {
+        let model = PopulationModel::LANTERNFISH;
+        assert_eq!(simulate(example(), &model, 18).len(), 26);
+        assert_eq!(simulate(example(), &model, 80).len(), 5934);
+        assert_eq!(
+            population_after_days(&example(), model, 256),
+            BigUint::from(26_984_457_539u64)
+        );
+    }
+
+    #[test]
+    fn test_matrix_matches_table() {
+        // Large enough that nothing wraps within 256 days.
+        let modulus = u64::MAX;
+        let models = [
+            PopulationModel::LANTERNFISH,
+            PopulationModel {
+                reset_timer: 4,
+                newborn_timer: 2,
+                max_lifespan: None,
+                offspring_per_spawn: 2,
+            },
+        ];
+        for model in &models {
+            for days in [0, 1, 18, 80, 100] {
+                assert_eq!(
+                    population_after_days_mod(&example(), model, days, modulus).map(BigUint::from),
+                    Some(population_after_days(&example(), *model, days)),
+                );
+            }
+        }
+        assert_eq!(
+            population_after_days_mod(&example(), &PopulationModel::LANTERNFISH, 256, modulus),
+            Some(26_984_457_539)
+        );
+    }
+
+    #[test]
+    fn test_matrix_modulo() {
+        let model = PopulationModel::LANTERNFISH;
+        let modulus = 1_000_000_007;
+        assert_eq!(
+            population_after_days_mod(&example(), &model, 256, modulus),
+            Some(26_984_457_539 % modulus)
+        );
+
+        // Stepping one day past 10^12 agrees with jumping there directly.
+        let matrix = TransitionMatrix::for_model(&model, modulus, 9).unwrap();
+        let buckets = timer_buckets(&example(), matrix.size, modulus);
+        let far = matrix.pow(1_000_000_000_000).apply(&buckets);
+        let next = sum_mod(matrix.apply(&far), modulus);
+        assert_eq!(
+            population_after_days_mod(&example(), &model, 1_000_000_000_001, modulus),
+            Some(next)
+        );
+    }
+
+    #[test]
+    fn test_matrix_zero_modulus() {
+        let model = PopulationModel::LANTERNFISH;
+        assert_eq!(TransitionMatrix::for_model(&model, 0, 9), None);
+        assert_eq!(population_after_days_mod(&example(), &model, 18, 0), None);
+        assert_eq!(population_after_days_mod(&[], &model, 0, 0), None);
+    }
+
+    #[test]
+    fn test_matrix_large_modulus() {
+        // The largest prime below 2^64, so every product of two entries is close to 2^128.
+        let modulus = 18_446_744_073_709_551_557;
+        let matrix = TransitionMatrix::for_model(&PopulationModel::LANTERNFISH, modulus, 9)
+            .unwrap()
+            .pow(1_000_003);
+        let buckets = vec![modulus - 1; 9];
+        let expected: Vec<u64> = (0..9)
+            .map(|row| {
+                let sum: BigUint = (0..9)
+                    .map(|col| BigUint::from(matrix.entries[row * 9 + col]) * buckets[col])
+                    .sum();
+                u64::try_from(sum % modulus).unwrap()
+            })
+            .collect();
+        assert_eq!(matrix.apply(&buckets), expected);
+    }
+
+    #[test]
+    fn test_matrix_rejects_mortal_fish() {
+        let model = PopulationModel {
+            max_lifespan: Some(10),
+            ..PopulationModel::LANTERNFISH
+        };
+        assert_eq!(population_after_days_mod(&example(), &model, 5, 97), None);
+    }
+
+    #[test]
+    fn test_exact_population() {
+        let models = [
+            PopulationModel::LANTERNFISH,
+            PopulationModel {
+                reset_timer: 3,
+                newborn_timer: 5,
+                max_lifespan: Some(12),
+                offspring_per_spawn: 2,
+            },
+            PopulationModel {
+                max_lifespan: Some(0),
+                ..PopulationModel::LANTERNFISH
+            },
+        ];
+        for model in &models {
+            for days in [0, 1, 18, 80] {
+                assert_eq!(
+                    exact_population_after_days(&example(), model, days),
+                    population_after_days(&example(), *model, days),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_exact_population_past_u64() {
+        let model = PopulationModel::LANTERNFISH;
+        let modulus = 1_000_000_007;
+        let population = exact_population_after_days(&example(), &model, 1000);
+        assert!(population > BigUint::from(u64::MAX));
+        assert_eq!(
+            population.clone() % modulus,
+            BigUint::from(population_after_days_mod(&example(), &model, 1000, modulus).unwrap())
+        );
+        assert_eq!(
+            summarize(&population, 1000),
+            format!("{} ({} digits)", population, population.to_string().len())
+        );
+    }
+
+    #[test]
+    fn test_summarize() {
+        let number = BigUint::from(1_644_874_076_764u64);
+        assert_eq!(summarize(&number, 20), "1644874076764 (13 digits)");
+        assert_eq!(summarize(&number, 4), "1644... (13 digits)");
+        assert_eq!(summarize(&BigUint::default(), 4), "0 (1 digits)");
+    }
+
+    #[test]
+    fn test_family_table() {
+        let mut table = FamilyTable::new(PopulationModel::LANTERNFISH, 18);
+        let expected: Vec<BigUint> = [
+            5u32, 5, 6, 7, 9, 10, 10, 10, 10, 11, 12, 15, 17, 19, 20, 20, 21, 22, 26,
+        ]
+        .iter()
+        .map(|&count| BigUint::from(count))
+        .collect();
+        assert_eq!(table.time_series(&example(), 18), expected);
+        assert_eq!(table.max_days(), 18);
+
+        // The same table answers for other inputs and grows on demand.
+        assert_eq!(table.population(&example(), 80), BigUint::from(5934u32));
+        assert_eq!(table.max_days(), 80);
+        assert_eq!(table.family_size(3, 0), BigUint::from(1u32));
+        assert_eq!(table.population(&[], 40), BigUint::default());
+
+        // Well past where u64 counts would overflow.
+        let series = table.time_series(&example(), 1000);
+        assert!(series[1000] > BigUint::from(u64::MAX));
+        assert_eq!(
+            series[1000],
+            exact_population_after_days(&example(), &PopulationModel::LANTERNFISH, 1000)
+        );
+    }
+
+    #[test]
+    fn test_histogram_series() {
+        let series = histogram_series(&example(), &PopulationModel::LANTERNFISH, 18);
+        let counts = |day: usize| -> Vec<u64> {
+            series[day]
+                .iter()
+                .map(|count| u64::try_from(count).unwrap())
+                .collect()
+        };
+        assert_eq!(series.len(), 19);
+        assert_eq!(counts(0), vec![0, 1, 1, 2, 1, 0, 0, 0, 0]);
+        // 6,0,6,4,5,6,0,1,1,2,6,0,1,1,1,2,2,3,3,4,6,7,8,8,8,8
+        assert_eq!(counts(18), vec![3, 5, 3, 2, 2, 1, 5, 1, 4]);
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let series = histogram_series(&example(), &PopulationModel::LANTERNFISH, 2);
+        let mut out = Vec::new();
+        write_csv(&series, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "day,timer_0,timer_1,timer_2,timer_3,timer_4,timer_5,timer_6,timer_7,timer_8,total\n\
+             0,0,1,1,2,1,0,0,0,0,5\n\
+             1,1,1,2,1,0,0,0,0,0,5\n\
+             2,1,2,1,0,0,0,1,0,1,6\n"
+        );
+    }
+
+    #[test]
+    fn test_growth_stats() {
+        let model = PopulationModel::LANTERNFISH;
+        let eigenvalue = dominant_eigenvalue(&model).unwrap();
+        // x^9 = x^2 + 1
+        assert!((eigenvalue.powi(9) - eigenvalue.powi(2) - 1.0).abs() < 1e-9);
+
+        let stats = GrowthStats::new(&histogram_series(&example(), &model, 2000), &model);
+        assert_eq!(stats.ratios.len(), 2000);
+        assert_eq!(stats.ratios[0], Some(1.0));
+        assert!((stats.ratios[1999].unwrap() - eigenvalue).abs() < 1e-9);
+        let day = stats.converged_after(1e-6).unwrap();
+        assert!(day > 1 && day < 2000);
+
+        let extinct = PopulationModel {
+            max_lifespan: Some(2),
+            ..model
+        };
+        let stats = GrowthStats::new(&histogram_series(&example(), &extinct, 5), &extinct);
+        assert_eq!(
+            stats.ratios,
+            vec![Some(1.0), Some(0.2), Some(1.0), Some(0.0), None]
+        );
+        assert_eq!(stats.converged_after(1e-6), None);
+    }
+
+    #[test]
+    fn test_timers_beyond_model() {
+        let fishes = || vec![Fish::new(5), Fish::new(9), Fish::new(1)];
+        let immortal = PopulationModel {
+            reset_timer: 2,
+            newborn_timer: 4,
+            max_lifespan: None,
+            offspring_per_spawn: 1,
+        };
+        let mortal = PopulationModel {
+            max_lifespan: Some(8),
+            ..immortal
+        };
+        for model in &[immortal, mortal] {
+            for days in [0, 3, 6, 10, 30] {
+                let expected = simulate(fishes(), model, days).len() as u64;
+                assert_eq!(
+                    population_after_days(&fishes(), *model, days as u64),
+                    BigUint::from(expected)
+                );
+                assert_eq!(
+                    exact_population_after_days(&fishes(), model, days as u64),
+                    BigUint::from(expected)
+                );
+                if model.max_lifespan.is_none() {
+                    assert_eq!(
+                        population_after_days_mod(&fishes(), model, days as u64, u64::MAX),
+                        Some(expected)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stillborn() {
+        let model = PopulationModel {
+            max_lifespan: Some(0),
+            ..PopulationModel::LANTERNFISH
+        };
+        for days in [0, 1, 10] {
+            assert_eq!(simulate(example(), &model, days).len(), 0);
+            assert_eq!(
+                population_after_days(&example(), model, days as u64),
+                BigUint::default()
+            );
+            assert_eq!(
+                exact_population_after_days(&example(), &model, days as u64),
+                BigUint::default()
+            );
+        }
+    }
+
+    #[test]
+    fn test_lifespan() {
+        // Fish that live for three days spawn once at most, and their young die before spawning.
+        let model = PopulationModel {
+            reset_timer: 5,
+            newborn_timer: 5,
+            max_lifespan: Some(3),
+            offspring_per_spawn: 1,
+        };
+        let counts: Vec<u64> = (0..8)
+            .map(|days| u64::try_from(population_after_days(&[Fish::new(0)], model, days)).unwrap())
+            .collect();
+        assert_eq!(counts, vec![1, 2, 2, 1, 0, 0, 0, 0]);
     }
 }
@@ -1,11 +1,23 @@
-use rand::seq::SliceRandom;
-use rand::thread_rng;
 use std::cmp::Ordering;
-use std::fs;
+use std::ops::RangeInclusive;
+use std::{env, fs};
 
 fn main() {
     println!("{}", part1());
     println!("{}", part2());
+    let args: Vec<String> = env::args().collect();
+    if let Some(idx) = args.iter().position(|arg| arg == "--cost") {
+        let cubic = Custom(|distance: u32| (distance as u64).pow(3));
+        let cost: &dyn FuelCost = match args.get(idx + 1).map(String::as_str) {
+            Some("linear") => &Linear,
+            Some("triangular") => &Triangular,
+            Some("quadratic") => &Quadratic,
+            Some("cubic") => &cubic,
+            _ => panic!("Expected --cost linear|triangular|quadratic|cubic"),
+        };
+        let (target, fuel) = optimal_alignment(&read_input(), cost);
+        println!("align on {} for {} fuel", target, fuel);
+    }
 }
 
 fn read_input() -> Vec<u32> {
@@ -16,96 +28,186 @@ fn read_input() -> Vec<u32> {
         .collect()
 }
 
-fn part1() -> u32 {
+fn part1() -> u64 {
     let positions = read_input();
-    let alignment_target = median(&positions);
-    fuel_cost_1(&positions, alignment_target)
+    optimal_alignment(&positions, &Linear).1
 }
 
-fn part2() -> u32 {
+fn part2() -> u64 {
     let positions = read_input();
-    // I don't fully understand WHY the average of the positions yields the
-    // lowest cost (whereas I have a decent understanding as to why this is
-    // the case for the median in part 1).  My intuition told me this might
-    // work and it turns out it does.
-    let alignment_target = average(&positions);
-    fuel_cost_2(&positions, alignment_target)
+    optimal_alignment(&positions, &Triangular).1
 }
 
-fn fuel_cost_1(positions: &[u32], alignment_target: u32) -> u32 {
-    positions
-        .iter()
-        .map(|p| {
-            if *p < alignment_target {
-                alignment_target - *p
-            } else {
-                *p - alignment_target
-            }
-        })
-        .sum()
+// Fuel a crab burns moving `distance` steps.  Costs must be convex for the solver to find the
+// optimum.
+trait FuelCost {
+    fn cost(&self, distance: u32) -> u64;
+
+    // Alignment targets the optimum is known to be among, if there's a closed form for it.
+    fn candidates(&self, _positions: &[u32]) -> Option<RangeInclusive<u32>> {
+        None
+    }
+}
+
+struct Linear;
+
+impl FuelCost for Linear {
+    fn cost(&self, distance: u32) -> u64 {
+        distance as u64
+    }
+
+    fn candidates(&self, positions: &[u32]) -> Option<RangeInclusive<u32>> {
+        let target = median(&mut positions.to_vec());
+        Some(target..=target)
+    }
+}
+
+struct Triangular;
+
+impl FuelCost for Triangular {
+    fn cost(&self, distance: u32) -> u64 {
+        let distance = distance as u64;
+        distance * (distance + 1) / 2
+    }
+
+    // The real-valued optimum lies within 0.5 of the mean.
+    fn candidates(&self, positions: &[u32]) -> Option<RangeInclusive<u32>> {
+        let mean = mean(positions);
+        Some((mean - 0.5).floor() as u32..=(mean + 0.5).ceil() as u32)
+    }
+}
+
+struct Quadratic;
+
+impl FuelCost for Quadratic {
+    fn cost(&self, distance: u32) -> u64 {
+        let distance = distance as u64;
+        distance * distance
+    }
+
+    fn candidates(&self, positions: &[u32]) -> Option<RangeInclusive<u32>> {
+        let mean = mean(positions);
+        Some(mean.floor() as u32..=mean.ceil() as u32)
+    }
 }
 
-fn fuel_cost_2(positions: &[u32], alignment_target: u32) -> u32 {
+// Any other cost function.  It's up to the caller to make sure it's convex.
+struct Custom<F>(F);
+
+impl<F: Fn(u32) -> u64> FuelCost for Custom<F> {
+    fn cost(&self, distance: u32) -> u64 {
+        (self.0)(distance)
+    }
+}
+
+fn total_fuel<C: FuelCost + ?Sized>(positions: &[u32], target: u32, cost: &C) -> u64 {
     positions
         .iter()
-        .map(|p| {
-            if *p < alignment_target {
-                alignment_target - *p
-            } else {
-                *p - alignment_target
-            }
-        })
-        .map(|distance| (distance * (distance + 1)) / 2)
+        .map(|p| cost.cost(p.abs_diff(target)))
         .sum()
 }
 
-fn average(values: &[u32]) -> u32 {
-    values.iter().sum::<u32>() / values.len() as u32
+// Returns an alignment target with the lowest total fuel, and that fuel.
+fn optimal_alignment<C: FuelCost + ?Sized>(positions: &[u32], cost: &C) -> (u32, u64) {
+    let fuel = |target| total_fuel(positions, target, cost);
+    if let Some(candidates) = cost.candidates(positions) {
+        return candidates
+            .map(|target| (target, fuel(target)))
+            .min_by_key(|&(target, fuel)| (fuel, target))
+            .unwrap();
+    }
+
+    // A sum of convex costs is convex, so the first target whose successor isn't cheaper is the
+    // optimum.
+    let mut low = *positions.iter().min().unwrap();
+    let mut high = *positions.iter().max().unwrap();
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if fuel(mid) <= fuel(mid + 1) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    (low, fuel(low))
+}
+
+fn mean(values: &[u32]) -> f64 {
+    values.iter().map(|&v| v as f64).sum::<f64>() / values.len() as f64
 }
 
-// Linear time median finding algo taken from https://rcoh.me/posts/linear-time-median-finding/
-fn median(values: &[u32]) -> u32 {
+// Leaves `values` reordered.
+fn median(values: &mut [u32]) -> u32 {
+    let mid = values.len() / 2;
     if values.len() % 2 == 1 {
-        quickselect(values, values.len() / 2)
+        select(values, mid)
     } else {
-        (quickselect(values, (values.len() / 2) - 1) + quickselect(values, values.len() / 2)) / 2
+        // Selecting the lower middle leaves everything after it at least as large.
+        let lower = select(values, mid - 1);
+        let upper = *values[mid..].iter().min().unwrap();
+        (lower + upper) / 2
     }
 }
 
-fn quickselect(values: &[u32], idx: usize) -> u32 {
-    if values.len() == 1 {
-        return values[0];
+// Moves the `idx`th smallest value to `values[idx]`, smaller ones before it and larger ones after,
+// and returns it.  Pivots are picked by median of medians, which keeps this linear in the worst
+// case without any randomness.
+fn select(values: &mut [u32], idx: usize) -> u32 {
+    if values.len() <= 5 {
+        values.sort_unstable();
+        return values[idx];
     }
 
-    let pivot = pivot(values);
-
-    let mut lows = Vec::new();
-    let mut pivots = Vec::new();
-    let mut highs = Vec::new();
-    for v in values {
-        match v.cmp(&pivot) {
-            Ordering::Less => lows.push(*v),
-            Ordering::Equal => pivots.push(*v),
-            Ordering::Greater => highs.push(*v),
-        }
+    let pivot = median_of_medians(values);
+    let (equal_start, equal_end) = partition(values, pivot);
+    if idx < equal_start {
+        select(&mut values[..equal_start], idx)
+    } else if idx < equal_end {
+        pivot
+    } else {
+        select(&mut values[equal_end..], idx - equal_end)
     }
+}
 
-    if idx < lows.len() {
-        quickselect(&lows, idx)
-    } else if idx < lows.len() + pivots.len() {
-        pivots[0]
-    } else {
-        quickselect(&highs, idx - (lows.len() + pivots.len()))
+fn median_of_medians(values: &mut [u32]) -> u32 {
+    let mut num_groups = 0;
+    for start in (0..values.len()).step_by(5) {
+        let end = (start + 5).min(values.len());
+        values[start..end].sort_unstable();
+        values.swap(num_groups, start + (end - start) / 2);
+        num_groups += 1;
     }
+    select(&mut values[..num_groups], num_groups / 2)
 }
 
-fn pivot(values: &[u32]) -> u32 {
-    *values.choose(&mut thread_rng()).unwrap()
+// Three-way partition around `pivot`.  Returns the range holding values equal to it.
+fn partition(values: &mut [u32], pivot: u32) -> (usize, usize) {
+    let mut less = 0;
+    let mut idx = 0;
+    let mut greater = values.len();
+    while idx < greater {
+        match values[idx].cmp(&pivot) {
+            Ordering::Less => {
+                values.swap(less, idx);
+                less += 1;
+                idx += 1;
+            }
+            Ordering::Equal => idx += 1,
+            Ordering::Greater => {
+                greater -= 1;
+                values.swap(idx, greater);
+            }
+        }
+    }
+    (less, greater)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::seq::SliceRandom;
+    use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+    use std::time::Instant;
 
     #[test]
     fn test_part1() {
@@ -116,4 +218,144 @@ mod tests {
     fn test_part2() {
         assert_eq!(part2(), 105461913);
     }
+
+    const EXAMPLE: [u32; 10] = [16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
+
+    fn brute_force<C: FuelCost + ?Sized>(positions: &[u32], cost: &C) -> (u32, u64) {
+        let low = *positions.iter().min().unwrap();
+        let high = *positions.iter().max().unwrap();
+        (low..=high)
+            .map(|target| (target, total_fuel(positions, target, cost)))
+            .min_by_key(|&(target, fuel)| (fuel, target))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_example() {
+        assert_eq!(optimal_alignment(&EXAMPLE, &Linear), (2, 37));
+        assert_eq!(optimal_alignment(&EXAMPLE, &Triangular), (5, 168));
+        assert_eq!(total_fuel(&EXAMPLE, 2, &Triangular), 206);
+        assert_eq!(
+            optimal_alignment(&EXAMPLE, &Quadratic),
+            brute_force(&EXAMPLE, &Quadratic)
+        );
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let inputs: [&[u32]; 5] = [
+            &EXAMPLE,
+            &[5],
+            &[0, 1],
+            &[0, 0, 0, 10],
+            &[3, 100, 7, 7, 42, 0, 19, 64, 8, 1, 1, 99],
+        ];
+        let costs: [&dyn FuelCost; 5] = [
+            &Linear,
+            &Triangular,
+            &Quadratic,
+            &Custom(|d: u32| (d as u64).pow(3)),
+            &Custom(|d: u32| (d as u64).saturating_sub(3)),
+        ];
+        for positions in &inputs {
+            for cost in &costs {
+                let (target, fuel) = optimal_alignment(positions, *cost);
+                assert_eq!(fuel, brute_force(positions, *cost).1, "{:?}", positions);
+                assert_eq!(fuel, total_fuel(positions, target, *cost));
+            }
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let mut rng = StdRng::seed_from_u64(50);
+        for len in [1, 2, 5, 6, 11, 64, 1000] {
+            for max in [3, 1_000_000] {
+                let values: Vec<u32> = (0..len).map(|_| rng.gen_range(0, max)).collect();
+                let mut sorted = values.clone();
+                sorted.sort_unstable();
+                for idx in [0, len / 3, len / 2, len - 1] {
+                    let mut scratch = values.clone();
+                    assert_eq!(select(&mut scratch, idx), sorted[idx]);
+                    assert_eq!(scratch[idx], sorted[idx]);
+                    assert!(scratch[..idx].iter().all(|&v| v <= sorted[idx]));
+                    assert!(scratch[idx..].iter().all(|&v| v >= sorted[idx]));
+                }
+                let expected = if len % 2 == 1 {
+                    sorted[len / 2]
+                } else {
+                    (sorted[len / 2 - 1] + sorted[len / 2]) / 2
+                };
+                assert_eq!(median(&mut values.clone()), expected);
+            }
+        }
+    }
+
+    // The allocating, randomized quickselect `select` replaced, kept around to benchmark against.
+    // Taken from https://rcoh.me/posts/linear-time-median-finding/
+    fn quickselect_median(values: &[u32]) -> u32 {
+        if values.len() % 2 == 1 {
+            quickselect(values, values.len() / 2)
+        } else {
+            (quickselect(values, (values.len() / 2) - 1) + quickselect(values, values.len() / 2))
+                / 2
+        }
+    }
+
+    fn quickselect(values: &[u32], idx: usize) -> u32 {
+        if values.len() == 1 {
+            return values[0];
+        }
+
+        let pivot = pivot(values);
+
+        let mut lows = Vec::new();
+        let mut pivots = Vec::new();
+        let mut highs = Vec::new();
+        for v in values {
+            match v.cmp(&pivot) {
+                Ordering::Less => lows.push(*v),
+                Ordering::Equal => pivots.push(*v),
+                Ordering::Greater => highs.push(*v),
+            }
+        }
+
+        if idx < lows.len() {
+            quickselect(&lows, idx)
+        } else if idx < lows.len() + pivots.len() {
+            pivots[0]
+        } else {
+            quickselect(&highs, idx - (lows.len() + pivots.len()))
+        }
+    }
+
+    fn pivot(values: &[u32]) -> u32 {
+        *values.choose(&mut thread_rng()).unwrap()
+    }
+
+    // cargo test --release --bin day_07 -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_median() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let values: Vec<u32> = (0..1_000_001).map(|_| rng.gen_range(0, 2000)).collect();
+        let time = |name: &str, f: &dyn Fn(Vec<u32>) -> u32| {
+            let start = Instant::now();
+            let mut result = 0;
+            for _ in 0..10 {
+                result = f(values.clone());
+            }
+            println!("{:>14}: {:?}", name, start.elapsed() / 10);
+            result
+        };
+        let expected = time("sort_unstable", &|mut values| {
+            values.sort_unstable();
+            values[values.len() / 2]
+        });
+        assert_eq!(
+            time("quickselect", &|values| quickselect_median(&values)),
+            expected
+        );
+        assert_eq!(time("select", &|mut values| median(&mut values)), expected);
+    }
 }
@@ -177,8 +177,8 @@ fn infer_mapping(signal_bundles: &[SignalBundle], mapping: &mut [SignalBundle; 1
     // These single-letter variables each represet a single segment of the display.  Each SignalBundle
     // should have exactly 1 bit set.  We deduce which signals correspond to which display segments
     // here.
-    let a = mapping[7] - mapping[1];
-    let g = intersection235 - (mapping[4] | mapping[7]);
+    let _a = mapping[7] - mapping[1];
+    let _g = intersection235 - (mapping[4] | mapping[7]);
     let b = (mapping[4] - mapping[1]) & intersection069;
     let f = mapping[1] & intersection069;
     let d = mapping[4] - mapping[1] - b;
@@ -1,6 +1,5 @@
 use std::fs;
 
-use itertools::Chunk;
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum ChunkType {
     Paren,